@@ -1,73 +1,723 @@
-use std::collections::BTreeMap;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+    process::exit,
+    rc::Rc,
+};
+
+use colored::Colorize;
 
 use crate::{
-    filetypes::FileType,
-    parser::{ComparisonOperator, LogicalExpression, LogicalOperator, Query},
+    filetypes::{Dialect, FileType, Layout},
+    glob,
+    parameters::Parameters,
+    parser::{
+        AggregateFunction, AggregateFunctionKind, ComparisonOperator, CompoundQuery, FileInfo,
+        LogicalExpression, LogicalOperator, OrderBy, PredicateValue, Query, SelectItem,
+        SetOperator, TableSource,
+    },
+    temporal::{self, Timestamp},
 };
 
-use self::{csv::CsvExecutor, xlsx::XlsxExecutor};
+use self::{csv::CsvExecutor, json::JsonExecutor, workbook::WorkbookExecutor};
 
+mod aggregate;
 mod csv;
-mod xlsx;
+mod json;
+mod reshape;
+mod sample;
+mod window;
+mod workbook;
+
+pub type Row = BTreeMap<String, serde_json::Value>;
+
+/// The headers and rows of a single sheet, with each row keyed by header
+pub struct Table<'a> {
+    pub headers: Vec<String>,
+    pub rows: Box<dyn Iterator<Item = Row> + 'a>,
+}
 
 pub trait Executor {
-    fn execute_query(&mut self, query: &Query) -> Result<String, serde_json::Error>;
+    fn read_table(&mut self, file: &FileInfo) -> Table<'_>;
+}
+
+/// A fully read table that can be scanned more than once
+pub struct ResultSet {
+    pub headers: Vec<String>,
+    pub rows: Vec<Row>,
+}
+
+/// State shared by every part of a query while it runs
+#[derive(Clone)]
+pub struct Context {
+    pub now: Timestamp,
+    parameters: Parameters,
+    /// relative file paths are resolved against this directory, e.g. that of a script
+    directory: Option<PathBuf>,
+    /// used for the options a file in FROM does not set itself
+    dialect: Dialect,
+    layout: Layout,
+    /// tables defined with WITH, keyed by lowercase name
+    tables: HashMap<String, Rc<ResultSet>>,
+}
+
+impl Context {
+    pub fn new(
+        now: Timestamp,
+        parameters: Parameters,
+        directory: Option<PathBuf>,
+        dialect: Dialect,
+        layout: Layout,
+    ) -> Self {
+        Self {
+            now,
+            parameters,
+            directory,
+            dialect,
+            layout,
+            tables: HashMap::new(),
+        }
+    }
+
+    /// Where a file in FROM is, relative paths are resolved against the directory of the script
+    fn resolve(&self, path: &str) -> PathBuf {
+        match &self.directory {
+            Some(directory) if Path::new(path).is_relative() => directory.join(path),
+            _ => PathBuf::from(path),
+        }
+    }
+
+    /// Looks up a table defined with WITH that a FROM clause refers to
+    fn table(&self, file: &FileInfo) -> Option<Table<'static>> {
+        if file.sheet.is_some() || file.table.is_some() {
+            return None;
+        }
+
+        let table = Rc::clone(self.tables.get(&file.path.to_lowercase())?);
+        Some(Table {
+            headers: table.headers.clone(),
+            rows: Box::new(table.rows.clone().into_iter()),
+        })
+    }
 }
 
 pub trait JsonValue {
     fn to_value(&self) -> serde_json::Value;
 }
 
-pub fn get_executor(path: &str, filetype: FileType) -> Box<dyn Executor> {
+pub fn get_executor(
+    path: &str,
+    filetype: FileType,
+    dialect: &Dialect,
+    layout: &Layout,
+) -> Box<dyn Executor> {
     match filetype {
-        FileType::SingleSheetFileType(_) => Box::new(CsvExecutor::new(path)),
-        FileType::MultiSheetFiletype(_) => Box::new(XlsxExecutor::new(path)),
+        FileType::SingleSheetFileType(filetype) if filetype.is_json() => {
+            Box::new(JsonExecutor::new(path))
+        }
+        FileType::SingleSheetFileType(filetype) => {
+            Box::new(CsvExecutor::new(path, &filetype, dialect, layout))
+        }
+        FileType::MultiSheetFiletype(filetype) => {
+            Box::new(WorkbookExecutor::new(path, &filetype, layout))
+        }
+    }
+}
+
+fn open_executor(file: &FileInfo, context: &Context) -> Box<dyn Executor> {
+    match FileType::parse_to_filetype(file.path.rsplit('.').next()) {
+        Some(filetype) => {
+            if matches!(&filetype, FileType::MultiSheetFiletype(_))
+                && file.sheet.is_none()
+                && file.table.is_none()
+            {
+                eprintln!(
+                    "{} a sheet or table is required for {} files",
+                    "error:".red().bold(),
+                    filetype.to_string().bold()
+                );
+                exit(1);
+            }
+
+            let dialect = file.dialect.or(&context.dialect);
+            let layout = file.layout.or(&context.layout);
+            get_executor(
+                &context.resolve(file.path).to_string_lossy(),
+                filetype,
+                &dialect,
+                &layout,
+            )
+        }
+        None => {
+            eprintln!("{} unsupported filetype", "error:".red().bold());
+            exit(1)
+        }
+    }
+}
+
+pub fn execute(query: &CompoundQuery, context: &Context) -> Table<'static> {
+    match query {
+        CompoundQuery::Query(query) => execute_query(query, context),
+        CompoundQuery::With(with) => {
+            // each table is read once here, no matter how often it is referenced
+            let mut context = context.clone();
+            for cte in &with.ctes {
+                let table = execute(&cte.query, &context);
+                context.tables.insert(
+                    cte.name.to_lowercase(),
+                    Rc::new(ResultSet {
+                        headers: table.headers,
+                        rows: table.rows.collect(),
+                    }),
+                );
+            }
+
+            execute(&with.query, &context)
+        }
+        CompoundQuery::SetOperation(operation) => {
+            let left = execute(&operation.left, context);
+            let right = execute(&operation.right, context);
+            if left.headers.len() != right.headers.len() {
+                eprintln!(
+                    "{} both sides of a set operation must return the same number of columns, found {} and {}",
+                    "error:".red().bold(),
+                    left.headers.len(),
+                    right.headers.len()
+                );
+                exit(1);
+            }
+
+            // the columns of the right side are renamed to those at the same position on the left
+            let right_headers = right.headers;
+            let right = right
+                .rows
+                .map(|mut row| {
+                    right_headers
+                        .iter()
+                        .zip(&left.headers)
+                        .map(|(r, l)| (l.clone(), row.remove(r).unwrap_or(serde_json::Value::Null)))
+                        .collect::<Row>()
+                })
+                .collect();
+
+            let rows = operation
+                .operator
+                .apply(&left.headers, left.rows.collect(), right);
+            Table {
+                headers: left.headers,
+                rows: Box::new(rows.into_iter()),
+            }
+        }
     }
 }
 
-impl LogicalExpression {
+/// Runs a subquery that must produce a single column and returns the values of that column
+fn execute_subquery(query: &CompoundQuery, context: &Context) -> Vec<serde_json::Value> {
+    let table = execute(query, context);
+    if table.headers.len() != 1 {
+        eprintln!(
+            "{} subquery must return exactly one column, found {}",
+            "error:".red().bold(),
+            table.headers.len()
+        );
+        exit(1);
+    }
+
+    table
+        .rows
+        .map(|row| row.into_values().next().unwrap_or(serde_json::Value::Null))
+        .collect()
+}
+
+impl SetOperator {
+    /// Combines two result sets whose rows have the same columns
+    fn apply(&self, headers: &[String], left: Vec<Row>, right: Vec<Row>) -> Vec<Row> {
+        let key = |row: &Row| row_key(headers, row);
+
+        match self {
+            SetOperator::UnionAll => left.into_iter().chain(right).collect(),
+            SetOperator::Union => distinct(headers, left.into_iter().chain(right)),
+            SetOperator::Intersect => {
+                let right = right.iter().map(key).collect::<HashSet<Vec<String>>>();
+                distinct(
+                    headers,
+                    left.into_iter().filter(|row| right.contains(&key(row))),
+                )
+            }
+            SetOperator::Except => {
+                let right = right.iter().map(key).collect::<HashSet<Vec<String>>>();
+                distinct(
+                    headers,
+                    left.into_iter().filter(|row| !right.contains(&key(row))),
+                )
+            }
+        }
+    }
+}
+
+/// Removes duplicate rows, keeping the first occurrence of each
+fn distinct(headers: &[String], rows: impl Iterator<Item = Row>) -> Vec<Row> {
+    let mut seen: HashSet<Vec<String>> = HashSet::new();
+    rows.filter(|row| seen.insert(row_key(headers, row)))
+        .collect()
+}
+
+/// Cells are compared like in IN, so `5.0` read from a sheet equals `5` read from a csv
+fn row_key(headers: &[String], row: &Row) -> Vec<String> {
+    headers
+        .iter()
+        .map(|header| value_key(row.get(header).unwrap_or(&serde_json::Value::Null)))
+        .collect()
+}
+
+pub fn execute_query(query: &Query, context: &Context) -> Table<'static> {
+    let mut executor;
+    let mut executors: Vec<Box<dyn Executor>>;
+    let table = match &query.from {
+        TableSource::File(file) => match context.table(file) {
+            Some(table) => table,
+            // a file whose name only looks like a pattern, e.g. `report[1].csv`, is read as is
+            None if glob::is_glob(file.path) && !context.resolve(file.path).is_file() => {
+                let directory = context.directory.as_deref().unwrap_or(Path::new(""));
+                let paths = glob::files(file.path, directory);
+                if paths.is_empty() {
+                    eprintln!("{} no file matches '{}'", "error:".red().bold(), file.path);
+                    exit(1);
+                }
+
+                let files = paths
+                    .iter()
+                    .map(|path| FileInfo { path, ..*file })
+                    .collect::<Vec<FileInfo>>();
+                executors = files
+                    .iter()
+                    .map(|file| open_executor(file, context))
+                    .collect();
+
+                let tables = paths
+                    .iter()
+                    .cloned()
+                    .zip(files.iter().zip(executors.iter_mut()))
+                    .map(|(path, (file, executor))| (path, executor.read_table(file)))
+                    .collect();
+                union_tables(tables, "_file")
+            }
+            None => {
+                executor = open_executor(file, context);
+                executor.read_table(file)
+            }
+        },
+        TableSource::Subquery(subquery) => execute(subquery, context),
+    };
+
+    let source = match &query.from {
+        TableSource::File(FileInfo {
+            sheet: Some(sheet), ..
+        }) => format!("sheet '{}'", sheet),
+        TableSource::File(FileInfo {
+            table: Some(table), ..
+        }) => format!("table '{}'", table),
+        TableSource::File(file) => format!("file '{}'", file.path),
+        TableSource::Subquery(_) => "subquery".to_string(),
+    };
+
+    // sampled while the rows are read, so that only the sample is kept in memory
+    let table = match &query.sample {
+        Some(sample) => sample::apply(sample, table),
+        None => table,
+    };
+
+    let table = match &query.reshape {
+        Some(reshape) => {
+            if let Some(column) = find_missing_column(&reshape.referenced_columns(), &table.headers)
+            {
+                eprintln!(
+                    "{} column '{}' does not exist in {}",
+                    "error:".red().bold(),
+                    column,
+                    source
+                );
+                exit(1);
+            }
+
+            reshape::apply(reshape, table, context)
+        }
+        None => table,
+    };
+    let grouped = !query.group_by.sets.is_empty()
+        || query
+            .columns
+            .iter()
+            .any(|item| matches!(item, SelectItem::Aggregate(_)));
+
+    // QUALIFY reads the rows the select list sees, along with the results of window functions
+    let mut qualify_columns = vec![];
+    if let Some(qualify) = &query.qualify {
+        let computed = query
+            .columns
+            .iter()
+            .filter_map(|item| match item {
+                SelectItem::Window(window) => Some(window.name()),
+                _ => None,
+            })
+            .chain(qualify.windows.iter().map(|window| window.name()))
+            .collect::<Vec<&str>>();
+
+        qualify_columns.extend(
+            qualify
+                .conditions
+                .referenced_columns()
+                .into_iter()
+                .filter(|column| !computed.contains(column)),
+        );
+        for window in &qualify.windows {
+            qualify_columns.extend(window.referenced_columns());
+        }
+    }
+
+    // verify that every column read from the spreadsheet exists in it
+    let mut columns = query.group_by.columns.clone();
+    if let Some(conditions) = &query.conditions {
+        columns.extend(conditions.referenced_columns());
+    }
+    for item in &query.columns {
+        match item {
+            SelectItem::Aggregate(aggregate) => columns.extend(aggregate.referenced_columns()),
+            SelectItem::Column(column) if !grouped => columns.push(column),
+            SelectItem::Window(window) if !grouped => columns.extend(window.referenced_columns()),
+            _ => {}
+        }
+    }
+    if !grouped {
+        columns.extend(&qualify_columns);
+    }
+    if let Some(column) = find_missing_column(&columns, &table.headers) {
+        eprintln!(
+            "{} column '{}' does not exist in {}",
+            "error:".red().bold(),
+            column,
+            source
+        );
+        exit(1);
+    }
+
+    // after grouping only the group columns and the aggregates are left
+    if grouped {
+        let mut available = query
+            .group_by
+            .columns
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<String>>();
+        for item in &query.columns {
+            match item {
+                SelectItem::Wildcard => {
+                    eprintln!(
+                        "{} {} cannot be used with GROUP BY or aggregates",
+                        "error:".red().bold(),
+                        "*".bold()
+                    );
+                    exit(1);
+                }
+                SelectItem::Aggregate(AggregateFunction {
+                    function: AggregateFunctionKind::Grouping(arguments),
+                    ..
+                }) if arguments
+                    .iter()
+                    .any(|a| !query.group_by.columns.contains(a)) =>
+                {
+                    eprintln!(
+                        "{} arguments of GROUPING must be GROUP BY columns",
+                        "error:".red().bold()
+                    );
+                    exit(1);
+                }
+                SelectItem::Aggregate(aggregate) => available.push(aggregate.name().to_string()),
+                _ => {}
+            }
+        }
+
+        let mut columns = qualify_columns;
+        for item in &query.columns {
+            match item {
+                SelectItem::Column(column) => columns.push(*column),
+                SelectItem::Window(window) => columns.extend(window.referenced_columns()),
+                _ => {}
+            }
+        }
+        if let Some(column) = find_missing_column(&columns, &available) {
+            eprintln!(
+                "{} column '{}' must appear in GROUP BY or be used in an aggregate",
+                "error:".red().bold(),
+                column
+            );
+            exit(1);
+        }
+    }
+
+    let headers = query
+        .columns
+        .iter()
+        .flat_map(|item| match item {
+            SelectItem::Wildcard => table.headers.clone(),
+            item => vec![item.name().to_string()],
+        })
+        .collect::<Vec<String>>();
+
+    let full_rows = table
+        .rows
+        .filter(|row| match &query.conditions {
+            Some(logical_expression) => {
+                LogicalExpression::evaluate_conditions(logical_expression, row, context)
+            }
+            None => true,
+        })
+        .collect::<Vec<Row>>();
+
+    let full_rows = if grouped {
+        aggregate::group(query, full_rows, context)
+    } else {
+        full_rows
+    };
+
+    let window_values = query
+        .columns
+        .iter()
+        .filter_map(|item| match item {
+            SelectItem::Window(window) => {
+                Some((window.name(), window::evaluate(window, &full_rows)))
+            }
+            _ => None,
+        })
+        .collect::<Vec<(&str, Vec<serde_json::Value>)>>();
+
+    let qualify_values = match &query.qualify {
+        Some(qualify) => qualify
+            .windows
+            .iter()
+            .map(|window| (window.name(), window::evaluate(window, &full_rows)))
+            .collect::<Vec<(&str, Vec<serde_json::Value>)>>(),
+        None => vec![],
+    };
+
+    let mut rows: Vec<Row> = vec![];
+    for (i, full_row) in full_rows.iter().enumerate() {
+        if let Some(qualify) = &query.qualify {
+            let mut scope = full_row.clone();
+            for (name, values) in window_values.iter().chain(&qualify_values) {
+                scope.insert(name.to_string(), values[i].clone());
+            }
+
+            if !LogicalExpression::evaluate_conditions(&qualify.conditions, &scope, context) {
+                continue;
+            }
+        }
+
+        let mut row = Row::new();
+
+        for item in &query.columns {
+            match item {
+                SelectItem::Wildcard => row.extend(full_row.clone()),
+                SelectItem::Column(column) => {
+                    row.insert(column.to_string(), full_row[*column].clone());
+                }
+                SelectItem::Aggregate(aggregate) => {
+                    let name = aggregate.name();
+                    row.insert(name.to_string(), full_row[name].clone());
+                }
+                SelectItem::Window(_) => {}
+            }
+        }
+
+        for (name, values) in &window_values {
+            row.insert(name.to_string(), values[i].clone());
+        }
+
+        if !row.is_empty() {
+            rows.push(row);
+        }
+    }
+
+    Table {
+        headers,
+        rows: Box::new(rows.into_iter()),
+    }
+}
+
+/// Stacks tables on top of each other, matching their columns by name. Columns a table does
+/// not have are null in its rows, and `column` holds the name each row came from.
+pub fn union_tables<'a>(tables: Vec<(String, Table<'a>)>, column: &str) -> Table<'a> {
+    let mut headers: Vec<String> = vec![];
+    for (_, table) in &tables {
+        for header in &table.headers {
+            if !headers.contains(header) {
+                headers.push(header.clone());
+            }
+        }
+    }
+
+    let row_headers = headers.clone();
+    let row_column = column.to_string();
+    let rows = tables.into_iter().flat_map(move |(name, table)| {
+        let (headers, column) = (row_headers.clone(), row_column.clone());
+        table.rows.map(move |mut row| {
+            for header in &headers {
+                row.entry(header.clone()).or_insert(serde_json::Value::Null);
+            }
+            row.insert(column.clone(), serde_json::Value::String(name.clone()));
+            row
+        })
+    });
+
+    headers.push(column.to_string());
+    Table {
+        headers,
+        rows: Box::new(rows),
+    }
+}
+
+/// Drops the last `n` items while still streaming the others
+pub fn skip_last<T>(items: impl Iterator<Item = T>, n: usize) -> impl Iterator<Item = T> {
+    let mut buffer = VecDeque::with_capacity(n + 1);
+    items.filter_map(move |item| {
+        buffer.push_back(item);
+        if buffer.len() > n {
+            buffer.pop_front()
+        } else {
+            None
+        }
+    })
+}
+
+fn find_missing_column<'a>(columns: &[&'a str], available: &[String]) -> Option<&'a str> {
+    columns
+        .iter()
+        .find(|column| !available.iter().any(|h| h == *column))
+        .copied()
+}
+
+/// Orders rows by a list of ORDER BY columns
+pub fn compare_rows(order_by: &[OrderBy], left: &Row, right: &Row) -> Ordering {
+    for order_by in order_by {
+        let ordering = compare_values(&left[order_by.column], &right[order_by.column]);
+        let ordering = if order_by.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// Orders cells the way ORDER BY does: booleans, then numbers, then strings, with nulls last
+pub fn compare_values(left: &serde_json::Value, right: &serde_json::Value) -> Ordering {
+    fn rank(value: &serde_json::Value) -> u8 {
+        match value {
+            serde_json::Value::Bool(_) => 0,
+            serde_json::Value::Number(_) => 1,
+            serde_json::Value::String(_) => 2,
+            serde_json::Value::Array(_) | serde_json::Value::Object(_) => 3,
+            serde_json::Value::Null => 4,
+        }
+    }
+
+    match (left, right) {
+        (serde_json::Value::Bool(l), serde_json::Value::Bool(r)) => l.cmp(r),
+        (serde_json::Value::Number(_), serde_json::Value::Number(_)) => json_to_number(left)
+            .partial_cmp(&json_to_number(right))
+            .unwrap_or(Ordering::Equal),
+        (serde_json::Value::String(l), serde_json::Value::String(r)) => l.cmp(r),
+        _ => rank(left)
+            .cmp(&rank(right))
+            .then_with(|| left.to_string().cmp(&right.to_string())),
+    }
+}
+
+impl ComparisonOperator {
+    fn matches(&self, ordering: Ordering) -> bool {
+        match self {
+            ComparisonOperator::Equal => ordering == Ordering::Equal,
+            ComparisonOperator::NotEqual => ordering != Ordering::Equal,
+            ComparisonOperator::GreaterThan => ordering == Ordering::Greater,
+            ComparisonOperator::LessThan => ordering == Ordering::Less,
+            ComparisonOperator::GreaterThanOrEqual => ordering != Ordering::Less,
+            ComparisonOperator::LessThanOrEqual => ordering != Ordering::Greater,
+        }
+    }
+}
+
+impl LogicalExpression<'_> {
     fn evaluate_conditions(
         logical_expression: &LogicalExpression,
         row: &BTreeMap<String, serde_json::Value>,
+        context: &Context,
     ) -> bool {
         match logical_expression {
             LogicalExpression::Predicate(predicate) => {
                 let row_value = row.get(&predicate.column).unwrap();
-                let compare_to = &str_to_json_value(&predicate.value);
-
-                let left = json_to_number(row_value);
-                let right = json_to_number(compare_to);
-
-                match predicate.operator {
-                    ComparisonOperator::Equal => match (left, right) {
-                        (Some(left), Some(right)) => left == right,
-                        _ => row_value.to_string() == compare_to.to_string(),
-                    },
-                    ComparisonOperator::NotEqual => match (left, right) {
-                        (Some(left), Some(right)) => left != right,
-                        _ => row_value.to_string() != compare_to.to_string(),
-                    },
-                    ComparisonOperator::GreaterThan => match (left, right) {
-                        (Some(left), Some(right)) => left > right,
-                        _ => row_value.to_string() > compare_to.to_string(),
-                    },
-                    ComparisonOperator::LessThan => match (left, right) {
-                        (Some(left), Some(right)) => left < right,
-                        _ => row_value.to_string() < compare_to.to_string(),
-                    },
-                    ComparisonOperator::GreaterThanOrEqual => match (left, right) {
-                        (Some(left), Some(right)) => left >= right,
-                        _ => row_value.to_string() >= compare_to.to_string(),
-                    },
-                    ComparisonOperator::LessThanOrEqual => match (left, right) {
-                        (Some(left), Some(right)) => left <= right,
-                        _ => row_value.to_string() <= compare_to.to_string(),
-                    },
+
+                let ordering = match &predicate.value {
+                    PredicateValue::Literal(value) => {
+                        compare_cells(row_value, &str_to_json_value(value))
+                    }
+                    PredicateValue::Parameter(parameter) => {
+                        compare_cells(row_value, context.parameters.get(parameter))
+                    }
+                    PredicateValue::Temporal(expression) => {
+                        temporal::compare_to_cell(row_value, &expression.evaluate(&context.now))
+                    }
+                    PredicateValue::Subquery(subquery) => {
+                        let compare_to = subquery.value.get_or_init(|| {
+                            let mut values = execute_subquery(&subquery.query, context);
+                            if values.len() > 1 {
+                                eprintln!(
+                                    "{} subquery used as a value returned more than one row",
+                                    "error:".red().bold()
+                                );
+                                exit(1);
+                            }
+                            values.pop().unwrap_or(serde_json::Value::Null)
+                        });
+
+                        compare_cells(row_value, compare_to)
+                    }
+                };
+
+                match ordering {
+                    Some(ordering) => predicate.operator.matches(ordering),
+                    None => false,
+                }
+            }
+            LogicalExpression::In(predicate) => {
+                let row_value = row.get(&predicate.column).unwrap();
+                let values = predicate.values.get_or_init(|| {
+                    execute_subquery(&predicate.query, context)
+                        .iter()
+                        .map(value_key)
+                        .collect()
+                });
+
+                if row_value.is_null() {
+                    return false;
+                }
+
+                let found = values.contains(&value_key(row_value));
+                if predicate.negated {
+                    // like SQL, NOT IN never matches when the subquery returned a null
+                    !found && !values.contains(&value_key(&serde_json::Value::Null))
+                } else {
+                    found
                 }
             }
             LogicalExpression::Condition(condition) => {
-                let left = LogicalExpression::evaluate_conditions(&condition.left, row);
-                let right = LogicalExpression::evaluate_conditions(&condition.right, row);
+                let left = LogicalExpression::evaluate_conditions(&condition.left, row, context);
+                let right = LogicalExpression::evaluate_conditions(&condition.right, row, context);
 
                 match condition.operator {
                     LogicalOperator::And => left && right,
@@ -78,8 +728,30 @@ impl LogicalExpression {
     }
 }
 
+fn compare_cells(left: &serde_json::Value, right: &serde_json::Value) -> Option<Ordering> {
+    match (json_to_number(left), json_to_number(right)) {
+        (Some(left), Some(right)) => left.partial_cmp(&right),
+        _ => Some(left.to_string().cmp(&right.to_string())),
+    }
+}
+
+/// A key that is equal for cells that compare equal, so that `1` and `1.0` match
+fn value_key(value: &serde_json::Value) -> String {
+    match json_to_number(value) {
+        Some(n) => n.to_string(),
+        None => value.to_string(),
+    }
+}
+
+pub fn float_to_json(value: f64) -> serde_json::Value {
+    match serde_json::Number::from_f64(value) {
+        Some(n) => serde_json::Value::Number(n),
+        None => serde_json::Value::Null,
+    }
+}
+
 pub fn str_to_json_value(value: &str) -> serde_json::Value {
-    if value == "" {
+    if value.is_empty() {
         return serde_json::Value::Null;
     }
 
@@ -103,7 +775,7 @@ pub fn str_to_json_value(value: &str) -> serde_json::Value {
     }
 }
 
-fn json_to_number(value: &serde_json::Value) -> Option<f64> {
+pub fn json_to_number(value: &serde_json::Value) -> Option<f64> {
     match value {
         serde_json::Value::Number(n) => n.as_f64(),
         _ => None,
@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+
+use colored::Colorize;
+
+use crate::parser::{
+    AggregateFunction, AggregateFunctionKind, LogicalExpression, OrderBy, Query, SelectItem,
+};
+
+use super::{compare_rows, compare_values, float_to_json, Context, Row};
+
+/// Collapses the rows into one row per group of every grouping set, holding the group columns
+/// and the result of every aggregate in the select list. Columns that are not part of a
+/// grouping set are null in its rows. Without GROUP BY there is a single group.
+pub fn group(query: &Query, rows: Vec<Row>, context: &Context) -> Vec<Row> {
+    let aggregates = query
+        .columns
+        .iter()
+        .filter_map(|item| match item {
+            SelectItem::Aggregate(aggregate) => Some(aggregate),
+            _ => None,
+        })
+        .collect::<Vec<&AggregateFunction>>();
+    let mut skipped = vec![0; aggregates.len()];
+
+    let sets = if query.group_by.sets.is_empty() {
+        vec![vec![]]
+    } else {
+        query.group_by.sets.clone()
+    };
+
+    let mut grouped = vec![];
+    for (i, set) in sets.iter().enumerate() {
+        for rows in groups(set, &rows) {
+            let mut row = Row::new();
+            for column in &query.group_by.columns {
+                let value = match rows.first() {
+                    Some(first) if set.contains(column) => first[*column].clone(),
+                    _ => serde_json::Value::Null,
+                };
+                row.insert(column.to_string(), value);
+            }
+
+            for (aggregate, skipped) in aggregates.iter().zip(skipped.iter_mut()) {
+                // every grouping set sees every row, so cells are only counted in the first one
+                let mut ignored = 0;
+                let skipped = if i == 0 { skipped } else { &mut ignored };
+                row.insert(
+                    aggregate.name().to_string(),
+                    evaluate(aggregate, &rows, set, skipped, context),
+                );
+            }
+
+            grouped.push(row);
+        }
+    }
+
+    for (aggregate, skipped) in aggregates.iter().zip(skipped) {
+        warn_skipped(aggregate, skipped);
+    }
+
+    grouped
+}
+
+pub fn warn_skipped(aggregate: &AggregateFunction, skipped: usize) {
+    if skipped > 0 {
+        eprintln!(
+            "{} {} skipped {} non-numeric cell{}",
+            "warning:".yellow().bold(),
+            aggregate.name(),
+            skipped,
+            if skipped == 1 { "" } else { "s" }
+        );
+    }
+}
+
+/// Splits the rows by the values of the columns in `set`, keeping the order groups were seen in
+fn groups(set: &[&str], rows: &[Row]) -> Vec<Vec<Row>> {
+    let mut groups: Vec<Vec<Row>> = vec![];
+    let mut keys: HashMap<String, usize> = HashMap::new();
+
+    for row in rows {
+        let key = set
+            .iter()
+            .map(|column| row[*column].clone())
+            .collect::<Vec<serde_json::Value>>();
+        let key = serde_json::Value::Array(key).to_string();
+
+        match keys.get(&key) {
+            Some(group) => groups[*group].push(row.clone()),
+            None => {
+                keys.insert(key, groups.len());
+                groups.push(vec![row.clone()]);
+            }
+        }
+    }
+
+    // the grand total has a row even when there is nothing to aggregate
+    if groups.is_empty() && set.is_empty() {
+        groups.push(vec![]);
+    }
+
+    groups
+}
+
+/// The value of an aggregate over `rows`, where `set` is the grouping set they belong to
+pub fn evaluate(
+    aggregate: &AggregateFunction,
+    rows: &[Row],
+    set: &[&str],
+    skipped: &mut usize,
+    context: &Context,
+) -> serde_json::Value {
+    let filtered;
+    let rows = match &aggregate.filter {
+        Some(filter) => {
+            filtered = rows
+                .iter()
+                .filter(|row| LogicalExpression::evaluate_conditions(filter, row, context))
+                .cloned()
+                .collect::<Vec<Row>>();
+            &filtered
+        }
+        None => rows,
+    };
+
+    match &aggregate.function {
+        AggregateFunctionKind::Grouping(columns) => serde_json::Value::from(
+            columns
+                .iter()
+                .fold(0, |mask, column| (mask << 1) | !set.contains(column) as u64),
+        ),
+        AggregateFunctionKind::Count(None) => serde_json::Value::from(rows.len()),
+        AggregateFunctionKind::Count(Some(column)) => {
+            serde_json::Value::from(rows.iter().filter(|row| !row[*column].is_null()).count())
+        }
+        AggregateFunctionKind::Sum(column) => sum(rows, column, skipped),
+        AggregateFunctionKind::Avg(column) => {
+            let numbers = numbers(rows, column, skipped);
+            mean(&numbers).map_or(serde_json::Value::Null, float_to_json)
+        }
+        AggregateFunctionKind::Min(column) => rows
+            .iter()
+            .map(|row| &row[*column])
+            .filter(|value| !value.is_null())
+            .min_by(|a, b| compare_values(a, b))
+            .cloned()
+            .unwrap_or(serde_json::Value::Null),
+        AggregateFunctionKind::Max(column) => rows
+            .iter()
+            .map(|row| &row[*column])
+            .filter(|value| !value.is_null())
+            .max_by(|a, b| compare_values(a, b))
+            .cloned()
+            .unwrap_or(serde_json::Value::Null),
+        AggregateFunctionKind::Median(column) => {
+            percentile_cont(numbers(rows, column, skipped), 0.5)
+        }
+        AggregateFunctionKind::PercentileCont(fraction, column) => {
+            percentile_cont(numbers(rows, column, skipped), *fraction)
+        }
+        AggregateFunctionKind::PercentileDisc(fraction, column) => {
+            let mut numbers = numbers(rows, column, skipped);
+            if numbers.is_empty() {
+                return serde_json::Value::Null;
+            }
+
+            numbers.sort_by(|a, b| a.total_cmp(b));
+            let position = ((fraction * numbers.len() as f64).ceil() as usize).max(1) - 1;
+            float_to_json(numbers[position])
+        }
+        AggregateFunctionKind::StddevSamp(column) => variance(&numbers(rows, column, skipped), 1)
+            .map_or(serde_json::Value::Null, |v| float_to_json(v.sqrt())),
+        AggregateFunctionKind::StddevPop(column) => variance(&numbers(rows, column, skipped), 0)
+            .map_or(serde_json::Value::Null, |v| float_to_json(v.sqrt())),
+        AggregateFunctionKind::VarSamp(column) => variance(&numbers(rows, column, skipped), 1)
+            .map_or(serde_json::Value::Null, float_to_json),
+        AggregateFunctionKind::VarPop(column) => variance(&numbers(rows, column, skipped), 0)
+            .map_or(serde_json::Value::Null, float_to_json),
+        AggregateFunctionKind::Corr(y, x) => {
+            // only rows where both cells are numbers take part
+            let pairs = rows
+                .iter()
+                .filter_map(|row| {
+                    let y = to_number(&row[*y], skipped)?;
+                    let x = to_number(&row[*x], skipped)?;
+                    Some((y, x))
+                })
+                .collect::<Vec<(f64, f64)>>();
+
+            correlation(&pairs).map_or(serde_json::Value::Null, float_to_json)
+        }
+        AggregateFunctionKind::ArrayAgg(column, order_by) => {
+            if rows.is_empty() {
+                return serde_json::Value::Null;
+            }
+
+            serde_json::Value::Array(
+                sorted(rows, order_by)
+                    .iter()
+                    .map(|row| row[*column].clone())
+                    .collect(),
+            )
+        }
+        AggregateFunctionKind::StringAgg(column, separator, order_by) => {
+            let values = sorted(rows, order_by)
+                .iter()
+                .filter_map(|row| match &row[*column] {
+                    serde_json::Value::Null => None,
+                    serde_json::Value::String(s) => Some(s.to_string()),
+                    value => Some(value.to_string()),
+                })
+                .collect::<Vec<String>>();
+
+            if values.is_empty() {
+                serde_json::Value::Null
+            } else {
+                serde_json::Value::String(values.join(separator))
+            }
+        }
+    }
+}
+
+fn sorted<'a>(rows: &'a [Row], order_by: &[OrderBy]) -> Vec<&'a Row> {
+    let mut rows = rows.iter().collect::<Vec<&Row>>();
+    rows.sort_by(|a, b| compare_rows(order_by, a, b));
+    rows
+}
+
+/// An integer when every summed cell is one and the total fits, a float otherwise
+fn sum(rows: &[Row], column: &str, skipped: &mut usize) -> serde_json::Value {
+    let mut integer = Some(0i64);
+    let mut float = 0.0;
+    let mut summed = 0;
+
+    for row in rows {
+        let value = &row[column];
+        let number = match to_number(value, skipped) {
+            Some(number) => number,
+            None => continue,
+        };
+
+        let cell = to_integer(value);
+        integer = integer
+            .zip(cell)
+            .and_then(|(total, cell)| total.checked_add(cell));
+        float += number;
+        summed += 1;
+    }
+
+    match integer {
+        _ if summed == 0 => serde_json::Value::Null,
+        Some(total) => serde_json::Value::from(total),
+        None => float_to_json(float),
+    }
+}
+
+/// The numeric cells of a column. Strings holding a number, which spreadsheets often store,
+/// are converted, nulls are ignored and anything else is counted in `skipped`.
+fn numbers(rows: &[Row], column: &str, skipped: &mut usize) -> Vec<f64> {
+    rows.iter()
+        .filter_map(|row| to_number(&row[column], skipped))
+        .collect()
+}
+
+pub fn to_number(value: &serde_json::Value, skipped: &mut usize) -> Option<f64> {
+    let number = match value {
+        serde_json::Value::Null => return None,
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => s.trim().parse::<f64>().ok().filter(|n| n.is_finite()),
+        _ => None,
+    };
+
+    if number.is_none() {
+        *skipped += 1;
+    }
+
+    number
+}
+
+/// The integer a cell holds, like `to_number` a string holding one counts
+pub fn to_integer(value: &serde_json::Value) -> Option<i64> {
+    match value {
+        serde_json::Value::Number(n) => n.as_i64(),
+        serde_json::Value::String(s) => s.trim().parse::<i64>().ok(),
+        _ => None,
+    }
+}
+
+fn mean(numbers: &[f64]) -> Option<f64> {
+    if numbers.is_empty() {
+        None
+    } else {
+        Some(numbers.iter().sum::<f64>() / numbers.len() as f64)
+    }
+}
+
+/// `ddof` is 1 for the sample variance and 0 for the population variance
+fn variance(numbers: &[f64], ddof: usize) -> Option<f64> {
+    if numbers.len() <= ddof {
+        return None;
+    }
+
+    let mean = mean(numbers)?;
+    let squares = numbers.iter().map(|n| (n - mean).powi(2)).sum::<f64>();
+
+    Some(squares / (numbers.len() - ddof) as f64)
+}
+
+fn correlation(pairs: &[(f64, f64)]) -> Option<f64> {
+    let ys = pairs.iter().map(|(y, _)| *y).collect::<Vec<f64>>();
+    let xs = pairs.iter().map(|(_, x)| *x).collect::<Vec<f64>>();
+    let (mean_y, mean_x) = (mean(&ys)?, mean(&xs)?);
+
+    let covariance = pairs
+        .iter()
+        .map(|(y, x)| (y - mean_y) * (x - mean_x))
+        .sum::<f64>();
+    let deviation = (variance(&ys, 0)? * variance(&xs, 0)?).sqrt() * pairs.len() as f64;
+
+    if deviation == 0.0 {
+        None
+    } else {
+        Some(covariance / deviation)
+    }
+}
+
+/// Linear interpolation between the closest ranks
+fn percentile_cont(mut numbers: Vec<f64>, fraction: f64) -> serde_json::Value {
+    if numbers.is_empty() {
+        return serde_json::Value::Null;
+    }
+
+    numbers.sort_by(|a, b| a.total_cmp(b));
+    let position = fraction * (numbers.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+
+    float_to_json(numbers[lower] + (numbers[upper] - numbers[lower]) * (position - lower as f64))
+}
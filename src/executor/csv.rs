@@ -1,18 +1,90 @@
-use std::{collections::BTreeMap, fs::File, process::exit};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    process::exit,
+};
 
 use colored::Colorize;
 
-use crate::parser::{LogicalExpression, Query};
+use crate::{
+    filetypes::{Dialect, Layout, SingleSheetFileType},
+    parser::FileInfo,
+    temporal::Timestamp,
+};
 
-use super::{str_to_json_value, Executor};
+use super::{skip_last, str_to_json_value, Executor, Row, Table};
+
+/// How much of a file is read to guess its dialect
+const SNIFF_BYTES: u64 = 16 * 1024;
+
+const DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
 
 pub struct CsvExecutor {
-    file: csv::Reader<File>,
+    file: csv::Reader<BufReader<File>>,
+    start: Start,
+    layout: Layout,
+}
+
+/// Where the header and the data of a file start
+struct Start {
+    /// title lines before the header
+    skip: usize,
+    header: bool,
 }
 
 impl CsvExecutor {
-    pub fn new(path: &str) -> Self {
-        let file = csv::ReaderBuilder::new().has_headers(false).from_path(path);
+    pub fn new(
+        path: &str,
+        filetype: &SingleSheetFileType,
+        dialect: &Dialect,
+        layout: &Layout,
+    ) -> Self {
+        let mut dialect = *dialect;
+        let mut start = Start {
+            skip: 0,
+            header: true,
+        };
+
+        if dialect.sniff != Some(false) {
+            if let Some(sample) = sample(path) {
+                // the extension of a tsv or psv file already says what the delimiter is
+                if dialect.delimiter.is_none()
+                    && matches!(
+                        filetype,
+                        SingleSheetFileType::CSV | SingleSheetFileType::TXT
+                    )
+                {
+                    dialect.delimiter = sniff_delimiter(&sample, &dialect);
+                }
+                let delimiter = dialect.delimiter.unwrap_or(filetype.delimiter());
+                dialect.quote = dialect.quote.or(sniff_quote(&sample, delimiter));
+
+                start = sniff_start(&records(&sample, delimiter, &dialect));
+            }
+        }
+        // a header row that is given replaces the guess
+        if layout.header_row.is_some() {
+            start = Start {
+                skip: 0,
+                header: true,
+            };
+        }
+        if let Some(header) = layout.header {
+            start.header = header;
+        }
+
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(dialect.delimiter.unwrap_or(filetype.delimiter()))
+            .escape(dialect.escape)
+            .comment(dialect.comment);
+        if let Some(quote) = dialect.quote {
+            builder.quote(quote);
+        }
+
+        let file = File::open(path);
 
         if file.is_err() {
             eprintln!(
@@ -24,62 +96,264 @@ impl CsvExecutor {
             exit(1);
         }
 
+        // lines above the header row are skipped before the csv reader sees them, so that
+        // blank lines are counted too
+        let mut reader = BufReader::new(file.unwrap());
+        for _ in 1..layout.header_row.unwrap_or(1) {
+            let mut line = vec![];
+            if !matches!(reader.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+                break;
+            }
+        }
+
         Self {
-            file: file.unwrap(),
+            file: builder.from_reader(reader),
+            start,
+            layout: *layout,
         }
     }
 }
 
 impl Executor for CsvExecutor {
-    fn execute_query(&mut self, query: &Query) -> Result<String, serde_json::Error> {
-        let mut records = self.file.records();
-        let headers = records
-            .next()
-            .unwrap()
-            .unwrap()
-            .iter()
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>();
-
-        // verify that all columns in the query exist in the spreadsheet
-        for column in &query.columns {
-            if !headers.contains(&column.to_string()) {
-                eprintln!(
-                    "{} column '{}' does not exist in file '{}'",
-                    "error:".red().bold(),
-                    column,
-                    query.file.path
+    fn read_table(&mut self, _file: &FileInfo) -> Table<'_> {
+        let records = self
+            .file
+            .records()
+            .skip(self.start.skip)
+            .map(|record| record.unwrap());
+        let mut records = skip_last(records, self.layout.skip_footer.unwrap_or(0)).peekable();
+
+        let headers = match records.peek() {
+            Some(record) if self.start.header => record.iter().map(|s| s.to_string()).collect(),
+            Some(record) => (1..=record.len()).map(|i| format!("c{}", i)).collect(),
+            None => vec![],
+        };
+        if self.start.header {
+            records.next();
+        }
+
+        let row_headers = headers.clone();
+        let rows = records.map(move |record| {
+            let mut row = Row::new();
+
+            for (i, header) in row_headers.iter().enumerate() {
+                row.insert(
+                    header.to_string(),
+                    str_to_json_value(record.get(i).unwrap_or_default()),
                 );
-                exit(1);
             }
+
+            row
+        });
+
+        Table {
+            headers,
+            rows: Box::new(rows),
         }
+    }
+}
 
-        let mut rows: Vec<BTreeMap<String, serde_json::Value>> = vec![];
+/// The first few KB of the file, cut after the last complete line
+fn sample(path: &str) -> Option<Vec<u8>> {
+    let mut sample = vec![];
+    File::open(path)
+        .ok()?
+        .take(SNIFF_BYTES)
+        .read_to_end(&mut sample)
+        .ok()?;
 
-        for record in records {
-            let record = record.unwrap();
-            let mut row: BTreeMap<String, serde_json::Value> = BTreeMap::new();
-            let mut full_row: BTreeMap<String, serde_json::Value> = BTreeMap::new();
+    if sample.len() as u64 == SNIFF_BYTES {
+        let end = sample.iter().rposition(|b| *b == b'\n')?;
+        sample.truncate(end + 1);
+    }
 
-            for (i, header) in headers.iter().enumerate() {
-                full_row.insert(header.to_string(), str_to_json_value(&record[i]));
-                if query.columns.contains(&header.as_str()) {
-                    row.insert(header.to_string(), str_to_json_value(&record[i]));
-                }
-            }
+    Some(sample)
+}
 
-            let should_add = match &query.conditions {
-                Some(logical_expression) => {
-                    LogicalExpression::evaluate_conditions(&logical_expression, &row)
-                }
-                None => true,
-            };
+fn records(sample: &[u8], delimiter: u8, dialect: &Dialect) -> Vec<Vec<String>> {
+    let mut builder = csv::ReaderBuilder::new();
+    builder
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .escape(dialect.escape)
+        .comment(dialect.comment);
+    if let Some(quote) = dialect.quote {
+        builder.quote(quote);
+    }
+
+    builder
+        .from_reader(sample)
+        .records()
+        .filter_map(|record| record.ok())
+        .map(|record| record.iter().map(|s| s.to_string()).collect())
+        .collect()
+}
 
-            if row.len() > 0 && should_add {
-                rows.push(row);
+/// The most common number of fields in a record and how many records have it
+fn field_count(records: &[Vec<String>]) -> (usize, usize) {
+    let mut counts: Vec<(usize, usize)> = vec![];
+    for record in records {
+        match counts
+            .iter_mut()
+            .find(|(fields, _)| *fields == record.len())
+        {
+            Some((_, n)) => *n += 1,
+            None => counts.push((record.len(), 1)),
+        }
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|(fields, n)| (*n, *fields))
+        .unwrap_or((0, 0))
+}
+
+/// The delimiter that splits the most records into the same number of fields
+fn sniff_delimiter(sample: &[u8], dialect: &Dialect) -> Option<u8> {
+    DELIMITERS
+        .iter()
+        .map(|delimiter| {
+            let (fields, n) = field_count(&records(sample, *delimiter, dialect));
+            (*delimiter, if fields > 1 { n } else { 0 })
+        })
+        .filter(|(_, score)| *score > 0)
+        // the first of the delimiters wins a tie
+        .rev()
+        .max_by_key(|(_, score)| *score)
+        .map(|(delimiter, _)| delimiter)
+}
+
+/// Single quotes are only used when they wrap more fields than double quotes do
+fn sniff_quote(sample: &[u8], delimiter: u8) -> Option<u8> {
+    let mut builder = csv::ReaderBuilder::new();
+    builder
+        .has_headers(false)
+        .flexible(true)
+        .quoting(false)
+        .delimiter(delimiter);
+
+    let mut single = 0;
+    let mut double = 0;
+    for record in builder.from_reader(sample).records().filter_map(|r| r.ok()) {
+        for field in record.iter() {
+            let field = field.trim();
+            if field.len() < 2 {
+                continue;
+            }
+            if field.starts_with('\'') && field.ends_with('\'') {
+                single += 1;
+            } else if field.starts_with('"') && field.ends_with('"') {
+                double += 1;
             }
         }
+    }
+
+    if single > double {
+        Some(b'\'')
+    } else {
+        None
+    }
+}
+
+/// Skips title lines, which have a single field above a table with more, and guesses whether
+/// the first row of the table is a header. A header is assumed unless every cell of the first
+/// row is a number or date, like the columns under it, as a row of names never is.
+fn sniff_start(records: &[Vec<String>]) -> Start {
+    let (fields, _) = field_count(records);
+    let skip = if fields > 1 {
+        records
+            .iter()
+            .position(|r| r.iter().filter(|cell| !cell.is_empty()).count() > 1)
+            .unwrap_or(0)
+    } else {
+        0
+    };
+
+    let (first, rest) = match records[skip.min(records.len())..].split_first() {
+        Some((first, rest)) if !rest.is_empty() => (first, rest),
+        _ => return Start { skip, header: true },
+    };
+
+    let data = first.iter().enumerate().all(|(i, cell)| {
+        let column = rest
+            .iter()
+            .filter_map(|record| record.get(i))
+            .filter(|cell| !cell.is_empty())
+            .collect::<Vec<&String>>();
+        let typed = column.iter().filter(|cell| is_typed(cell)).count();
+
+        is_typed(cell) && typed * 2 > column.len()
+    });
+
+    Start {
+        skip,
+        header: !data,
+    }
+}
+
+fn is_typed(cell: &str) -> bool {
+    !str_to_json_value(cell).is_string() || Timestamp::parse(cell).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How the start of a comma separated sample is sniffed, as `(skip, header)`
+    fn start(sample: &str) -> (usize, bool) {
+        let start = sniff_start(&records(sample.as_bytes(), b',', &Dialect::default()));
+        (start.skip, start.header)
+    }
+
+    #[test]
+    fn semicolons() {
+        let sample = b"name;amount;note\nalice;1,5;a, b\nbob;2,25;c\n";
+        assert_eq!(sniff_delimiter(sample, &Dialect::default()), Some(b';'));
+        assert_eq!(sniff_quote(sample, b';'), None);
+
+        let sample = b"name|amount\nalice|1\nbob|2\n";
+        assert_eq!(sniff_delimiter(sample, &Dialect::default()), Some(b'|'));
+        // a single column has no delimiter to find
+        assert_eq!(
+            sniff_delimiter(b"name\nalice\nbob\n", &Dialect::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn single_quotes() {
+        let sample = b"'name','note'\n'alice','a, b'\n'bob','c'\n";
+        assert_eq!(sniff_quote(sample, b','), Some(b'\''));
+        let sample = b"\"name\",note\n\"alice\",'s\n";
+        assert_eq!(sniff_quote(sample, b','), None);
+    }
+
+    #[test]
+    fn title_lines() {
+        assert_eq!(
+            start("Sales report\nExported 2024-01-31,\nname,amount\nalice,1\nbob,2\n"),
+            (2, true)
+        );
+        assert_eq!(start("name,amount\nalice,1\nbob,2\n"), (0, true));
+    }
+
+    #[test]
+    fn no_header() {
+        assert_eq!(
+            start("1,2024-01-01,3.5\n2,2024-01-02,4\n3,2024-01-03,\n"),
+            (0, false)
+        );
+        assert_eq!(start("Totals\n1,2\n3,4\n"), (1, false));
+    }
 
-        serde_json::to_string_pretty(&rows)
+    #[test]
+    fn numeric_header() {
+        // years above text are names
+        assert_eq!(start("2022,2023\nalice,bob\ncarol,dave\n"), (0, true));
+        // but above numbers they cannot be told apart from data
+        assert_eq!(start("2022,2023\n1,2\n3,4\n"), (0, false));
+        // a single name among them makes it a header
+        assert_eq!(start("id,2023\n1,2\n3,4\n"), (0, true));
     }
 }
@@ -0,0 +1,112 @@
+use std::{collections::BTreeSet, fs::File, io::BufReader, process::exit};
+
+use colored::Colorize;
+use serde_json::{Map, Value};
+
+use crate::parser::FileInfo;
+
+use super::{Executor, Row, Table};
+
+/// Reads a JSON array of objects, or one object per line as in NDJSON. Any number of either can
+/// follow each other, so the output of a script can be read back in.
+pub struct JsonExecutor {
+    path: String,
+}
+
+impl JsonExecutor {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+        }
+    }
+}
+
+impl Executor for JsonExecutor {
+    fn read_table(&mut self, _file: &FileInfo) -> Table<'_> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!(
+                    "{} failed to open {}, {}",
+                    "error:".red().bold(),
+                    self.path.bold(),
+                    e
+                );
+                exit(1);
+            }
+        };
+
+        let mut rows: Vec<Row> = vec![];
+        for value in serde_json::Deserializer::from_reader(BufReader::new(file)).into_iter() {
+            let objects = match value {
+                Ok(Value::Array(values)) => values,
+                Ok(value) => vec![value],
+                Err(e) => {
+                    eprintln!(
+                        "{} failed to read {}, {}",
+                        "error:".red().bold(),
+                        self.path.bold(),
+                        e
+                    );
+                    exit(1);
+                }
+            };
+
+            for object in objects {
+                match object {
+                    Value::Object(object) => {
+                        let mut row = Row::new();
+                        flatten(object, "", &mut row);
+                        rows.push(row);
+                    }
+                    value => {
+                        eprintln!(
+                            "{} expected an object in {}, found {}",
+                            "error:".red().bold(),
+                            self.path.bold(),
+                            value
+                        );
+                        exit(1);
+                    }
+                }
+            }
+        }
+
+        // objects need not have the same keys, the columns are those of every object
+        let headers = rows
+            .iter()
+            .flat_map(|row| row.keys().cloned())
+            .collect::<BTreeSet<String>>()
+            .into_iter()
+            .collect::<Vec<String>>();
+
+        let row_headers = headers.clone();
+        let rows = rows.into_iter().map(move |mut row| {
+            for header in &row_headers {
+                row.entry(header.clone()).or_insert(Value::Null);
+            }
+            row
+        });
+
+        Table {
+            headers,
+            rows: Box::new(rows),
+        }
+    }
+}
+
+/// Nested objects become columns named by their path, e.g. `{"a": {"b": 1}}` becomes `a.b`.
+/// Arrays are kept as they are.
+fn flatten(object: Map<String, Value>, prefix: &str, row: &mut Row) {
+    for (key, value) in object {
+        let key = format!("{}{}", prefix, key);
+        match value {
+            Value::Object(object) if !object.is_empty() => {
+                flatten(object, &format!("{}.", key), row)
+            }
+            value => {
+                row.insert(key, value);
+            }
+        }
+    }
+}
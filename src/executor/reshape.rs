@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use crate::parser::{Pivot, Reshape, Unpivot};
+
+use super::{aggregate, str_to_json_value, value_key, Context, Row, Table};
+
+/// Applies a PIVOT or UNPIVOT to the table read in FROM
+pub fn apply<'a>(reshape: &Reshape, table: Table<'a>, context: &Context) -> Table<'a> {
+    match reshape {
+        Reshape::Pivot(pivot) => self::pivot(pivot, table, context),
+        Reshape::Unpivot(unpivot) => self::unpivot(unpivot, table),
+    }
+}
+
+/// One row per distinct combination of the remaining columns, with the aggregate of the rows
+/// holding each listed value in a column of its own
+fn pivot<'a>(pivot: &Pivot, table: Table<'a>, context: &Context) -> Table<'a> {
+    let referenced = pivot.aggregate.referenced_columns();
+    let group_columns = table
+        .headers
+        .iter()
+        .filter(|h| *h != pivot.column && !referenced.contains(&h.as_str()))
+        .cloned()
+        .collect::<Vec<String>>();
+    let values = pivot
+        .values
+        .iter()
+        .map(|(value, _)| value_key(&str_to_json_value(value)))
+        .collect::<Vec<String>>();
+
+    // every group keeps the rows of each value apart, in the order groups were seen in
+    let mut groups: Vec<(Row, Vec<Vec<Row>>)> = vec![];
+    let mut keys: HashMap<String, usize> = HashMap::new();
+
+    for row in table.rows {
+        let group_row = group_columns
+            .iter()
+            .map(|column| (column.clone(), row[column].clone()))
+            .collect::<Row>();
+        let key = serde_json::to_string(&group_row).unwrap();
+
+        let group = match keys.get(&key) {
+            Some(group) => *group,
+            None => {
+                keys.insert(key, groups.len());
+                groups.push((group_row, vec![vec![]; values.len()]));
+                groups.len() - 1
+            }
+        };
+
+        let cell = &row[pivot.column];
+        if cell.is_null() {
+            continue;
+        }
+
+        let cell = value_key(cell);
+        if let Some(i) = values.iter().position(|value| *value == cell) {
+            groups[group].1[i].push(row);
+        }
+    }
+
+    let mut skipped = 0;
+    let rows = groups
+        .into_iter()
+        .map(|(mut row, rows)| {
+            for ((_, name), rows) in pivot.values.iter().zip(rows) {
+                row.insert(
+                    name.to_string(),
+                    aggregate::evaluate(&pivot.aggregate, &rows, &[], &mut skipped, context),
+                );
+            }
+            row
+        })
+        .collect::<Vec<Row>>();
+    aggregate::warn_skipped(&pivot.aggregate, skipped);
+
+    let mut headers = group_columns;
+    headers.extend(pivot.values.iter().map(|(_, name)| name.to_string()));
+
+    Table {
+        headers,
+        rows: Box::new(rows.into_iter()),
+    }
+}
+
+/// One row for every non-null cell in the listed columns, with the column name in `name`
+/// and the cell in `value`
+fn unpivot<'a>(unpivot: &Unpivot, table: Table<'a>) -> Table<'a> {
+    let columns = unpivot
+        .columns
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<String>>();
+    let (name, value) = (unpivot.name.to_string(), unpivot.value.to_string());
+
+    let mut headers = table
+        .headers
+        .into_iter()
+        .filter(|h| !columns.contains(h))
+        .collect::<Vec<String>>();
+    headers.push(name.clone());
+    headers.push(value.clone());
+
+    let rows = table.rows.flat_map(move |mut row| {
+        let cells = columns
+            .iter()
+            .map(|column| (column.clone(), row.remove(column)))
+            .collect::<Vec<(String, Option<serde_json::Value>)>>();
+
+        let (name, value) = (name.clone(), value.clone());
+        cells.into_iter().filter_map(move |(column, cell)| {
+            let cell = cell.filter(|cell| !cell.is_null())?;
+
+            let mut row = row.clone();
+            row.insert(name.clone(), serde_json::Value::String(column));
+            row.insert(value.clone(), cell);
+            Some(row)
+        })
+    });
+
+    Table {
+        headers,
+        rows: Box::new(rows),
+    }
+}
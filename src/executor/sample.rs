@@ -0,0 +1,87 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::parser::{Sample, SampleMethod};
+
+use super::{Row, Table};
+
+/// Applies TABLESAMPLE or USING SAMPLE to the rows as they are read
+pub fn apply<'a>(sample: &Sample, table: Table<'a>) -> Table<'a> {
+    let mut random = Random::new(sample.seed);
+
+    let rows: Box<dyn Iterator<Item = Row> + 'a> = match sample.method {
+        SampleMethod::Bernoulli(percentage) => Box::new(
+            table
+                .rows
+                .filter(move |_| random.next_f64() * 100.0 < percentage),
+        ),
+        SampleMethod::Reservoir(size) => {
+            Box::new(reservoir(table.rows, size, &mut random).into_iter())
+        }
+    };
+
+    Table {
+        headers: table.headers,
+        rows,
+    }
+}
+
+/// Algorithm R, keeps `size` rows chosen uniformly at random in the order they were read
+fn reservoir(rows: impl Iterator<Item = Row>, size: usize, random: &mut Random) -> Vec<Row> {
+    // the size can be far more than the rows there are, so it is not reserved up front
+    let mut reservoir: Vec<(usize, Row)> = Vec::new();
+
+    for (i, row) in rows.enumerate() {
+        if i < size {
+            reservoir.push((i, row));
+        } else {
+            let j = random.below(i as u64 + 1) as usize;
+            if j < size {
+                reservoir[j] = (i, row);
+            }
+        }
+    }
+
+    reservoir.sort_by_key(|(i, _)| *i);
+    reservoir.into_iter().map(|(_, row)| row).collect()
+}
+
+/// xorshift64*, sampling does not need anything stronger
+struct Random {
+    state: u64,
+}
+
+impl Random {
+    /// Seeds from the clock unless a seed is given with REPEATABLE
+    fn new(seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64)
+        });
+
+        // splitmix64 spreads small seeds over the whole state, which must not be zero
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        Self { state: z.max(1) }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number in `[0, 1)`
+    fn next_f64(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A number in `[0, n)`
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
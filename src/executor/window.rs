@@ -0,0 +1,220 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use crate::parser::{FrameBound, WindowFrame, WindowFunction, WindowFunctionKind};
+
+use colored::Colorize;
+
+use super::{
+    aggregate::{to_integer, to_number},
+    compare_rows, float_to_json, str_to_json_value, Row,
+};
+
+/// Computes a window function for every row, the result is in the same order as `rows`
+pub fn evaluate(window: &WindowFunction, rows: &[Row]) -> Vec<serde_json::Value> {
+    let mut values = vec![serde_json::Value::Null; rows.len()];
+    let mut skipped = 0;
+
+    for mut partition in partitions(window, rows) {
+        partition.sort_by(|a, b| compare_rows(&window.order_by, &rows[*a], &rows[*b]));
+
+        let partition_rows = partition.iter().map(|i| &rows[*i]).collect::<Vec<&Row>>();
+        for (position, value) in evaluate_partition(window, &partition_rows, &mut skipped)
+            .into_iter()
+            .enumerate()
+        {
+            values[partition[position]] = value;
+        }
+    }
+
+    if skipped > 0 {
+        eprintln!(
+            "{} {} skipped {} non-numeric cell{}",
+            "warning:".yellow().bold(),
+            if matches!(window.function, WindowFunctionKind::Avg(_)) {
+                "AVG"
+            } else {
+                "SUM"
+            },
+            skipped,
+            if skipped == 1 { "" } else { "s" }
+        );
+    }
+
+    values
+}
+
+/// Groups row indices by the PARTITION BY columns, keeping the order rows were read in
+fn partitions(window: &WindowFunction, rows: &[Row]) -> Vec<Vec<usize>> {
+    let mut partitions: Vec<Vec<usize>> = vec![];
+    let mut keys: HashMap<String, usize> = HashMap::new();
+
+    for (i, row) in rows.iter().enumerate() {
+        let key = window
+            .partition_by
+            .iter()
+            .map(|column| row[*column].clone())
+            .collect::<Vec<serde_json::Value>>();
+        let key = serde_json::Value::Array(key).to_string();
+
+        match keys.get(&key) {
+            Some(partition) => partitions[*partition].push(i),
+            None => {
+                keys.insert(key, partitions.len());
+                partitions.push(vec![i]);
+            }
+        }
+    }
+
+    partitions
+}
+
+fn evaluate_partition(
+    window: &WindowFunction,
+    rows: &[&Row],
+    skipped: &mut usize,
+) -> Vec<serde_json::Value> {
+    let peers = peer_groups(window, rows);
+
+    match &window.function {
+        WindowFunctionKind::RowNumber => (1..=rows.len()).map(serde_json::Value::from).collect(),
+        WindowFunctionKind::Rank => (0..rows.len())
+            .map(|i| serde_json::Value::from(peers[i].0 + 1))
+            .collect(),
+        WindowFunctionKind::DenseRank => {
+            let mut rank = 0;
+            (0..rows.len())
+                .map(|i| {
+                    if peers[i].0 == i {
+                        rank += 1;
+                    }
+                    serde_json::Value::from(rank)
+                })
+                .collect()
+        }
+        WindowFunctionKind::Lag(offset) | WindowFunctionKind::Lead(offset) => {
+            let default = match offset.default {
+                Some(default) => str_to_json_value(default),
+                None => serde_json::Value::Null,
+            };
+
+            (0..rows.len())
+                .map(|i| {
+                    let target = match window.function {
+                        WindowFunctionKind::Lag(_) => i.checked_sub(offset.offset),
+                        _ => Some(i.saturating_add(offset.offset)).filter(|t| *t < rows.len()),
+                    };
+
+                    match target {
+                        Some(target) => rows[target][offset.column].clone(),
+                        None => default.clone(),
+                    }
+                })
+                .collect()
+        }
+        WindowFunctionKind::FirstValue(column) => (0..rows.len())
+            .map(|i| {
+                let (start, end) = frame(window, &peers, i);
+                if start < end {
+                    rows[start][*column].clone()
+                } else {
+                    serde_json::Value::Null
+                }
+            })
+            .collect(),
+        WindowFunctionKind::Sum(column) | WindowFunctionKind::Avg(column) => {
+            // prefix sums so that running totals stay linear in the partition size. Cells are
+            // converted like in the SUM aggregate, and the sum of the integers is kept exactly
+            // so that a frame of integers sums to an integer whenever the total fits
+            let mut sums = vec![0.0];
+            let mut integers = vec![0i128];
+            let mut counts = vec![0];
+            let mut fractions = vec![0];
+
+            for row in rows {
+                let value = &row[*column];
+                let number = to_number(value, skipped);
+                let integer = number.and(to_integer(value));
+
+                sums.push(sums.last().unwrap() + number.unwrap_or(0.0));
+                integers.push(integers.last().unwrap() + integer.unwrap_or(0) as i128);
+                counts.push(counts.last().unwrap() + number.map_or(0, |_| 1));
+                fractions.push(
+                    fractions.last().unwrap() + (number.is_some() && integer.is_none()) as usize,
+                );
+            }
+
+            (0..rows.len())
+                .map(|i| {
+                    let (start, end) = frame(window, &peers, i);
+                    let (start, end) = (start, end.max(start));
+                    let sum = sums[end] - sums[start];
+                    let count = counts[end] - counts[start];
+
+                    if count == 0 {
+                        serde_json::Value::Null
+                    } else if matches!(window.function, WindowFunctionKind::Avg(_)) {
+                        float_to_json(sum / count as f64)
+                    } else if fractions[end] == fractions[start] {
+                        match i64::try_from(integers[end] - integers[start]) {
+                            Ok(total) => serde_json::Value::from(total),
+                            Err(_) => float_to_json(sum),
+                        }
+                    } else {
+                        float_to_json(sum)
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+/// For every row, the index of the first and one past the last row that ties with it in ORDER BY
+fn peer_groups(window: &WindowFunction, rows: &[&Row]) -> Vec<(usize, usize)> {
+    let mut peers = vec![(0, 0); rows.len()];
+    let mut start = 0;
+
+    while start < rows.len() {
+        let mut end = start + 1;
+        while end < rows.len()
+            && compare_rows(&window.order_by, rows[start], rows[end]) == Ordering::Equal
+        {
+            end += 1;
+        }
+
+        for peer in peers.iter_mut().take(end).skip(start) {
+            *peer = (start, end);
+        }
+        start = end;
+    }
+
+    peers
+}
+
+/// The rows visible to row `i` as a half open range of partition positions
+fn frame(window: &WindowFunction, peers: &[(usize, usize)], i: usize) -> (usize, usize) {
+    let len = peers.len();
+
+    match &window.frame {
+        Some(WindowFrame { start, end }) => {
+            let start = match start {
+                FrameBound::UnboundedPreceding => 0,
+                FrameBound::Preceding(n) => i.saturating_sub(*n),
+                FrameBound::CurrentRow => i,
+                FrameBound::Following(n) => i.saturating_add(*n),
+                FrameBound::UnboundedFollowing => len,
+            };
+            let end = match end {
+                FrameBound::UnboundedPreceding => 0,
+                FrameBound::Preceding(n) => (i + 1).saturating_sub(*n),
+                FrameBound::CurrentRow => i + 1,
+                FrameBound::Following(n) => i.saturating_add(*n).saturating_add(1),
+                FrameBound::UnboundedFollowing => len,
+            };
+
+            (start.min(len), end.min(len))
+        }
+        // without ORDER BY the frame is the whole partition, otherwise it ends at the last peer
+        None if window.order_by.is_empty() => (0, len),
+        None => (0, peers[i].1),
+    }
+}
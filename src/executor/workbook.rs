@@ -0,0 +1,332 @@
+use std::{collections::HashMap, fs::File, io::BufReader, process::exit};
+
+use crate::{
+    filetypes::{column_name, CellRange, Layout, MultiSheetFileType},
+    glob,
+    parser::{self, FileInfo},
+    temporal::Timestamp,
+};
+
+use super::{skip_last, union_tables, Executor, JsonValue, Row, Table};
+use calamine::{open_workbook, DataType, Error, Range, Reader, Sheets};
+use colored::Colorize;
+
+/// Reads any of the workbook formats calamine supports
+pub struct WorkbookExecutor {
+    workbook: Sheets<BufReader<File>>,
+    tables: HashMap<String, Range<DataType>>,
+    layout: Layout,
+    /// Whether calamine can be asked for the Excel tables of the workbook
+    has_tables: bool,
+}
+
+impl WorkbookExecutor {
+    pub fn new(path: &str, filetype: &MultiSheetFileType, layout: &Layout) -> Self {
+        let workbook = match open(path, filetype) {
+            Ok(wb) => wb,
+            Err(e) => {
+                eprintln!(
+                    "{} failed to open {}, {}",
+                    "error:".red().bold(),
+                    path.to_string().bold(),
+                    e
+                );
+                exit(1);
+            }
+        };
+
+        Self {
+            workbook,
+            tables: HashMap::new(),
+            layout: *layout,
+            has_tables: has_sheet_relationships(path),
+        }
+    }
+}
+
+impl JsonValue for DataType {
+    fn to_value(&self) -> serde_json::Value {
+        match self {
+            DataType::Empty => serde_json::Value::Null,
+            DataType::Bool(b) => serde_json::Value::Bool(*b),
+            DataType::Int(i) => serde_json::Value::Number(serde_json::Number::from(*i)),
+            DataType::Float(f) => {
+                serde_json::Value::Number(serde_json::Number::from_f64(*f).unwrap())
+            }
+            DataType::String(s) => serde_json::Value::String(s.to_string()),
+            DataType::DateTime(dt) => {
+                serde_json::Value::String(Timestamp::from_excel(*dt).to_string())
+            }
+            DataType::Error(e) => serde_json::Value::String(e.to_string()),
+            DataType::Duration(d) => serde_json::Value::String(d.to_string()),
+            DataType::DateTimeIso(dt) => serde_json::Value::String(dt.to_string()),
+            DataType::DurationIso(d) => serde_json::Value::String(d.to_string()),
+        }
+    }
+}
+
+/// Opens the workbook with the reader for its format
+fn open(path: &str, filetype: &MultiSheetFileType) -> Result<Sheets<BufReader<File>>, Error> {
+    Ok(match filetype {
+        MultiSheetFileType::XLS | MultiSheetFileType::XLA => {
+            Sheets::Xls(open_workbook(path).map_err(Error::Xls)?)
+        }
+        MultiSheetFileType::XLSX | MultiSheetFileType::XLSM | MultiSheetFileType::XLAM => {
+            Sheets::Xlsx(open_workbook(path).map_err(Error::Xlsx)?)
+        }
+        MultiSheetFileType::XLSB => Sheets::Xlsb(open_workbook(path).map_err(Error::Xlsb)?),
+        MultiSheetFileType::ODS => Sheets::Ods(open_workbook(path).map_err(Error::Ods)?),
+    })
+}
+
+impl WorkbookExecutor {
+    /// The used part of a sheet, read once and then cached
+    fn worksheet(&mut self, sheet: &str, path: &str) -> Range<DataType> {
+        if let Some(range) = self.tables.get(sheet) {
+            return range.clone();
+        }
+
+        let range = match self.workbook.worksheet_range(sheet) {
+            Some(Ok(range)) => range,
+            Some(Err(e)) => {
+                eprintln!(
+                    "{} failed to open sheet '{}', {}",
+                    "error:".red().bold(),
+                    sheet,
+                    e
+                );
+                exit(1);
+            }
+            None => {
+                eprintln!(
+                    "{} sheet '{}' does not exist in file '{}'",
+                    "error:".red().bold(),
+                    sheet,
+                    path
+                );
+                exit(1);
+            }
+        };
+        self.tables.insert(sheet.to_string(), range.clone());
+        range
+    }
+
+    /// Finds an Excel table by name, or else a named range
+    fn named_table(&mut self, name: &str, path: &str) -> Table<'static> {
+        if let Sheets::Xlsx(xlsx) = &mut self.workbook {
+            // a workbook without table metadata only has named ranges
+            let table = match xlsx.load_tables() {
+                Ok(_) if self.has_tables => xlsx
+                    .table_names()
+                    .into_iter()
+                    .find(|t| t.eq_ignore_ascii_case(name))
+                    .cloned(),
+                _ => None,
+            };
+
+            if let Some(table) = table {
+                return match xlsx.table_by_name(&table) {
+                    Some(Ok(table)) => {
+                        // the header row is not part of the data, its names are kept apart
+                        let headers = table.columns().to_vec();
+                        let rows = to_rows(
+                            &headers,
+                            table.data().rows(),
+                            self.layout.skip_footer.unwrap_or(0),
+                        );
+
+                        Table {
+                            headers,
+                            rows: Box::new(rows.into_iter()),
+                        }
+                    }
+                    Some(Err(e)) => {
+                        eprintln!(
+                            "{} failed to open table '{}', {}",
+                            "error:".red().bold(),
+                            name,
+                            e
+                        );
+                        exit(1);
+                    }
+                    // the table is on a sheet that is not in the workbook
+                    None => {
+                        eprintln!(
+                            "{} the sheet of table '{}' does not exist in file '{}'",
+                            "error:".red().bold(),
+                            name,
+                            path
+                        );
+                        exit(1);
+                    }
+                };
+            }
+        }
+
+        let reference = self
+            .workbook
+            .defined_names()
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, reference)| reference.to_string());
+
+        match reference {
+            Some(reference) => match parser::parse_reference(&reference) {
+                Some((sheet, cells)) => {
+                    let range = within(&self.worksheet(sheet, path), &cells);
+                    self.to_table(name, range)
+                }
+                None => {
+                    eprintln!(
+                        "{} named range '{}' refers to '{}', which is not a block of cells",
+                        "error:".red().bold(),
+                        name,
+                        reference
+                    );
+                    exit(1);
+                }
+            },
+            None => {
+                eprintln!(
+                    "{} table '{}' does not exist in file '{}'",
+                    "error:".red().bold(),
+                    name,
+                    path
+                );
+                exit(1);
+            }
+        }
+    }
+
+    fn read_sheet(&mut self, sheet: &str, file: &FileInfo) -> Table<'static> {
+        let range = self.worksheet(sheet, file.path);
+        let range = match file.range {
+            Some(cells) => within(&range, &cells),
+            None => range,
+        };
+
+        self.to_table(sheet, range)
+    }
+
+    fn to_table(&self, sheet: &str, range: Range<DataType>) -> Table<'static> {
+        // rows above the header row are skipped, the range itself starts at the first used row
+        let first_row = range.start().map_or(0, |(row, _)| row as usize);
+        let skip = match self.layout.header_row {
+            Some(header_row) if header_row - 1 < first_row => {
+                eprintln!(
+                    "{} row {} of sheet '{}' is empty",
+                    "error:".red().bold(),
+                    header_row,
+                    sheet
+                );
+                exit(1);
+            }
+            Some(header_row) => header_row - 1 - first_row,
+            None => 0,
+        };
+
+        let mut iter = range.rows().skip(skip);
+        let headers = if self.layout.header == Some(false) {
+            // named after the columns of the sheet, which need not start at A
+            let first = range.start().map_or(0, |(_, column)| column as usize);
+            (first..first + range.width()).map(column_name).collect()
+        } else {
+            match iter.next() {
+                Some(cells) => cells.iter().map(|h| h.to_string()).collect(),
+                None => vec![],
+            }
+        };
+
+        let rows = to_rows(&headers, iter, self.layout.skip_footer.unwrap_or(0));
+
+        Table {
+            headers,
+            rows: Box::new(rows.into_iter()),
+        }
+    }
+}
+
+/// The cells of a block that are in the used part of a sheet, whole columns like `A1:Z1048576`
+/// only read as far as the sheet goes
+fn within(range: &Range<DataType>, cells: &CellRange) -> Range<DataType> {
+    let (start, end) = match (range.start(), range.end()) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return Range::empty(),
+    };
+
+    let start = (start.0.max(cells.start.0), start.1.max(cells.start.1));
+    let end = (end.0.min(cells.end.0), end.1.min(cells.end.1));
+    if start.0 > end.0 || start.1 > end.1 {
+        return Range::empty();
+    }
+
+    range.range(start, end)
+}
+
+/// calamine 0.22 only keeps a list of tables once a sheet has relationships, which is where
+/// tables are linked from, and panics when asked for the tables of any other workbook
+fn has_sheet_relationships(path: &str) -> bool {
+    let archive = File::open(path)
+        .ok()
+        .and_then(|file| zip::ZipArchive::new(BufReader::new(file)).ok());
+
+    archive.is_some_and(|archive| {
+        archive
+            .file_names()
+            .any(|name| name.starts_with("xl/worksheets/_rels/") && name.ends_with(".rels"))
+    })
+}
+
+fn to_rows<'a>(
+    headers: &[String],
+    cells: impl Iterator<Item = &'a [DataType]>,
+    skip_footer: usize,
+) -> Vec<Row> {
+    skip_last(cells, skip_footer)
+        .map(|cells| {
+            let mut row = Row::new();
+            for (i, cell) in cells.iter().enumerate() {
+                row.insert(headers[i].to_string(), cell.to_value());
+            }
+            row
+        })
+        .collect()
+}
+
+impl Executor for WorkbookExecutor {
+    fn read_table(&mut self, file: &FileInfo) -> Table<'_> {
+        if let Some(name) = file.table {
+            return self.named_table(name, file.path);
+        }
+
+        let pattern = file.sheet.unwrap();
+        if !glob::is_glob(pattern) {
+            return self.read_sheet(pattern, file);
+        }
+
+        let sheets = self
+            .workbook
+            .sheet_names()
+            .into_iter()
+            .filter(|sheet| glob::matches(pattern, sheet))
+            .collect::<Vec<String>>();
+        if sheets.is_empty() {
+            eprintln!(
+                "{} no sheet matches '{}' in file '{}'",
+                "error:".red().bold(),
+                pattern,
+                file.path
+            );
+            exit(1);
+        }
+
+        let tables = sheets
+            .into_iter()
+            .map(|sheet| {
+                let table = self.read_sheet(&sheet, file);
+                (sheet, table)
+            })
+            .collect();
+        union_tables(tables, "_sheet")
+    }
+}
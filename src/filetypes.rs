@@ -1,5 +1,6 @@
 use core::fmt;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug)]
 pub enum MultiSheetFileType {
     ODS,
@@ -11,9 +12,48 @@ pub enum MultiSheetFileType {
     XLSX,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug)]
 pub enum SingleSheetFileType {
     CSV,
+    TSV,
+    TAB,
+    PSV,
+    TXT,
+    JSON,
+    NDJSON,
+    JSONL,
+}
+
+/// How the fields of a delimited text file are separated and quoted, options that are not set
+/// use the defaults of the filetype
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Dialect {
+    pub delimiter: Option<u8>,
+    pub quote: Option<u8>,
+    pub escape: Option<u8>,
+    pub comment: Option<u8>,
+    /// whether the delimiter, quote and header are guessed from the start of the file
+    pub sniff: Option<bool>,
+}
+
+/// A block of cells such as `B3:F200`, as zero based `(row, column)` positions
+#[derive(Debug, Clone, Copy)]
+pub struct CellRange {
+    pub start: (u32, u32),
+    pub end: (u32, u32),
+}
+
+/// Where the table is in a file or sheet
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Layout {
+    /// whether the first row holds the column names, without one the columns are named by
+    /// position, `c1, c2, ...` in text files and `A, B, ...` in sheets
+    pub header: Option<bool>,
+    /// the 1-based row the table starts at, rows above it are skipped
+    pub header_row: Option<usize>,
+    /// rows at the end that are not part of the table, e.g. totals
+    pub skip_footer: Option<usize>,
 }
 
 pub enum FileType {
@@ -24,8 +64,8 @@ pub enum FileType {
 impl fmt::Display for FileType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            FileType::MultiSheetFiletype(s) => write!(f, "{}", s.to_string()),
-            FileType::SingleSheetFileType(s) => write!(f, "{}", s.to_string()),
+            FileType::MultiSheetFiletype(s) => write!(f, "{}", s),
+            FileType::SingleSheetFileType(s) => write!(f, "{}", s),
         }
     }
 }
@@ -34,6 +74,13 @@ impl fmt::Display for SingleSheetFileType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             SingleSheetFileType::CSV => write!(f, "csv"),
+            SingleSheetFileType::TSV => write!(f, "tsv"),
+            SingleSheetFileType::TAB => write!(f, "tab"),
+            SingleSheetFileType::PSV => write!(f, "psv"),
+            SingleSheetFileType::TXT => write!(f, "txt"),
+            SingleSheetFileType::JSON => write!(f, "json"),
+            SingleSheetFileType::NDJSON => write!(f, "ndjson"),
+            SingleSheetFileType::JSONL => write!(f, "jsonl"),
         }
     }
 }
@@ -56,6 +103,13 @@ impl FileType {
     pub fn parse_to_filetype(candidate: Option<&str>) -> Option<FileType> {
         match candidate?.to_lowercase().as_str() {
             "csv" => Some(FileType::SingleSheetFileType(SingleSheetFileType::CSV)),
+            "tsv" => Some(FileType::SingleSheetFileType(SingleSheetFileType::TSV)),
+            "tab" => Some(FileType::SingleSheetFileType(SingleSheetFileType::TAB)),
+            "psv" => Some(FileType::SingleSheetFileType(SingleSheetFileType::PSV)),
+            "txt" => Some(FileType::SingleSheetFileType(SingleSheetFileType::TXT)),
+            "json" => Some(FileType::SingleSheetFileType(SingleSheetFileType::JSON)),
+            "ndjson" => Some(FileType::SingleSheetFileType(SingleSheetFileType::NDJSON)),
+            "jsonl" => Some(FileType::SingleSheetFileType(SingleSheetFileType::JSONL)),
             "xlsx" => Some(FileType::MultiSheetFiletype(MultiSheetFileType::XLSX)),
             "ods" => Some(FileType::MultiSheetFiletype(MultiSheetFileType::ODS)),
             "xla" => Some(FileType::MultiSheetFiletype(MultiSheetFileType::XLA)),
@@ -67,3 +121,85 @@ impl FileType {
         }
     }
 }
+
+impl SingleSheetFileType {
+    pub fn delimiter(&self) -> u8 {
+        match self {
+            SingleSheetFileType::CSV | SingleSheetFileType::TXT => b',',
+            SingleSheetFileType::TSV | SingleSheetFileType::TAB => b'\t',
+            SingleSheetFileType::PSV => b'|',
+            // not delimited, these are read as JSON
+            SingleSheetFileType::JSON
+            | SingleSheetFileType::NDJSON
+            | SingleSheetFileType::JSONL => b',',
+        }
+    }
+
+    pub fn is_json(&self) -> bool {
+        matches!(
+            self,
+            SingleSheetFileType::JSON | SingleSheetFileType::NDJSON | SingleSheetFileType::JSONL
+        )
+    }
+}
+
+impl Dialect {
+    /// Parses the value of an option, which must be a single ASCII character, `\t` or `tab`
+    pub fn parse_character(s: &str) -> Option<u8> {
+        match s {
+            "\\t" | "tab" => Some(b'\t'),
+            s if s.len() == 1 && s.is_ascii() => Some(s.as_bytes()[0]),
+            _ => None,
+        }
+    }
+
+    /// Takes the options that are not set from `other`
+    pub fn or(&self, other: &Dialect) -> Dialect {
+        Dialect {
+            delimiter: self.delimiter.or(other.delimiter),
+            quote: self.quote.or(other.quote),
+            escape: self.escape.or(other.escape),
+            comment: self.comment.or(other.comment),
+            sniff: self.sniff.or(other.sniff),
+        }
+    }
+}
+
+impl Layout {
+    /// Takes the options that are not set from `other`
+    pub fn or(&self, other: &Layout) -> Layout {
+        Layout {
+            header: self.header.or(other.header),
+            header_row: self.header_row.or(other.header_row),
+            skip_footer: self.skip_footer.or(other.skip_footer),
+        }
+    }
+}
+
+/// The letters of a zero based column index, e.g. `A` for 0 and `AA` for 26
+pub fn column_name(mut index: usize) -> String {
+    let mut name = vec![];
+    loop {
+        name.push(b'A' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+
+    name.iter().rev().map(|b| *b as char).collect()
+}
+
+/// The zero based index of column letters, e.g. 0 for `A` and 26 for `AA`
+pub fn column_index(name: &str) -> Option<usize> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    name.to_ascii_uppercase()
+        .bytes()
+        .try_fold(0usize, |index, b| {
+            index.checked_mul(26)?.checked_add((b - b'A') as usize + 1)
+        })
+        .map(|index| index - 1)
+}
@@ -0,0 +1,221 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Whether a sheet name or path is a pattern rather than a name
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Shell style matching, `*` matches any text, `?` any one character and `[a-z]` or `[!0-9]`
+/// one character in or out of a set
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let text = text.chars().collect::<Vec<char>>();
+
+    let (mut p, mut t) = (0, 0);
+    // where to retry from when the text after the last `*` does not match
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(p + 1),
+            Some('[') => match class(&pattern[p..], text[t]) {
+                Some((true, len)) => Some(p + len),
+                Some((false, _)) => None,
+                // an unclosed `[` is matched literally
+                None if text[t] == '[' => Some(p + 1),
+                None => None,
+            },
+            Some(c) if *c == text[t] => Some(p + 1),
+            _ => None,
+        };
+
+        match (step, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                t += 1;
+            }
+            (None, Some((star, start))) => {
+                // let the `*` take one more character
+                backtrack = Some((star, start + 1));
+                p = star + 1;
+                t = start + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Whether `c` is in the set at the start of the pattern, and how long the set is
+fn class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let negated = matches!(pattern.get(1), Some('!') | Some('^'));
+    let mut i = if negated { 2 } else { 1 };
+    let mut found = false;
+
+    // a `]` right after the `[` is part of the set
+    let mut first = true;
+    loop {
+        match pattern.get(i)? {
+            ']' if !first => break,
+            start => match (pattern.get(i + 1), pattern.get(i + 2)) {
+                (Some('-'), Some(end)) if *end != ']' => {
+                    found |= (*start..=*end).contains(&c);
+                    i += 3;
+                }
+                _ => {
+                    found |= *start == c;
+                    i += 1;
+                }
+            },
+        }
+        first = false;
+    }
+
+    Some((found != negated, i + 1))
+}
+
+/// The files that match a path pattern like `exports/2024-*.csv`, sorted. Relative patterns
+/// are matched in `base` but the paths are returned as they would be written in the pattern.
+pub fn files(pattern: &str, base: &Path) -> Vec<String> {
+    let mut candidates = vec![PathBuf::new()];
+
+    for component in Path::new(pattern).components() {
+        let name = component.as_os_str().to_string_lossy();
+        if !is_glob(&name) {
+            candidates.iter_mut().for_each(|path| path.push(&name[..]));
+            continue;
+        }
+
+        candidates = candidates
+            .into_iter()
+            .flat_map(|path| {
+                let directory = base.join(&path);
+                let directory = if directory.as_os_str().is_empty() {
+                    PathBuf::from(".")
+                } else {
+                    directory
+                };
+
+                let mut entries = vec![];
+                if let Ok(dir) = fs::read_dir(directory) {
+                    for entry in dir.flatten() {
+                        let entry = entry.file_name().to_string_lossy().to_string();
+                        // hidden files are only matched by a pattern that starts with a dot
+                        if entry.starts_with('.') && !name.starts_with('.') {
+                            continue;
+                        }
+                        if matches(&name, &entry) {
+                            entries.push(path.join(entry));
+                        }
+                    }
+                }
+                entries
+            })
+            .collect();
+    }
+
+    let mut files = candidates
+        .into_iter()
+        .filter(|path| base.join(path).is_file())
+        .map(|path| path.to_string_lossy().to_string())
+        .collect::<Vec<String>>();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything"));
+        assert!(matches("2023-*", "2023-01"));
+        assert!(!matches("2023-*", "2024-01"));
+        assert!(matches("?", "a"));
+        assert!(!matches("?", ""));
+        assert!(!matches("?", "ab"));
+        assert!(matches("202?-0?", "2024-01"));
+        assert!(matches("**a", "a"));
+    }
+
+    #[test]
+    fn backtracking() {
+        assert!(matches("a*b", "axxbyb"));
+        assert!(!matches("a*b", "axxbyc"));
+        assert!(matches("*.csv", "a.csv.csv"));
+        assert!(!matches("*.csv", "a.csv.bak"));
+        assert!(matches("*a*b*c", "xaybzabc"));
+        assert!(!matches("*a*b*c", "xaybzacb"));
+    }
+
+    #[test]
+    fn sets() {
+        assert!(matches("[a-c]x", "bx"));
+        assert!(!matches("[a-c]x", "dx"));
+        assert!(matches("[abc]", "c"));
+        assert!(matches("[!0-9]", "a"));
+        assert!(!matches("[!0-9]", "5"));
+        assert!(matches("[^0-9]", "a"));
+        assert!(!matches("[^0-9]", "5"));
+        // a `-` at the end of a set is a character
+        assert!(matches("[a-]", "-"));
+    }
+
+    #[test]
+    fn brackets() {
+        // a `]` right after the `[` is part of the set
+        assert!(matches("[]a]", "]"));
+        assert!(matches("[]a]", "a"));
+        assert!(!matches("[!]a]", "]"));
+        assert!(matches("[!]a]", "b"));
+        // an unclosed `[` is a character
+        assert!(matches("a[b", "a[b"));
+        assert!(!matches("a[b", "ab"));
+        assert!(matches("[", "["));
+    }
+
+    #[test]
+    fn files_skip_hidden_files() {
+        let base = std::env::temp_dir().join(format!("ssq-glob-{}", std::process::id()));
+        fs::create_dir_all(base.join("exports/old")).unwrap();
+        for file in [
+            "exports/2024-01.csv",
+            "exports/2024-02.csv",
+            "exports/2023-12.csv",
+            "exports/.2024-03.csv",
+            "exports/old/2024-04.csv",
+        ] {
+            fs::write(base.join(file), "id\n1\n").unwrap();
+        }
+
+        let files = |pattern: &str| files(pattern, &base);
+        assert_eq!(
+            files("exports/2024-*.csv"),
+            ["exports/2024-01.csv", "exports/2024-02.csv"]
+        );
+        assert_eq!(files("exports/.2024-*.csv"), ["exports/.2024-03.csv"]);
+        assert_eq!(files("*/*/2024-*.csv"), ["exports/old/2024-04.csv"]);
+        // directories are not files
+        assert!(files("exports/*").iter().all(|f| f.ends_with(".csv")));
+        assert!(files("missing/*.csv").is_empty());
+
+        let absolute = base.join("exports/2023-*.csv");
+        assert_eq!(
+            super::files(&absolute.to_string_lossy(), Path::new("")),
+            [base.join("exports/2023-12.csv").to_string_lossy()]
+        );
+
+        fs::remove_dir_all(base).unwrap();
+    }
+}
@@ -1,29 +1,123 @@
 use std::{
+    fs,
     io::{self, Read},
+    path::PathBuf,
     process::exit,
 };
 
 use clap::Parser;
 use colored::Colorize;
-use executor::get_executor;
-use filetypes::FileType;
-use parser::parse_query;
+use executor::{execute, Context, Row};
+use filetypes::{Dialect, Layout};
+use parameters::Parameters;
+use parser::{parse_script, strip_comments};
+use temporal::Timestamp;
 
 mod executor;
 mod filetypes;
+mod glob;
+mod parameters;
 pub mod parser;
+mod temporal;
 
 /// Run SQL queries on spreadsheets and outputs the result in JSON
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// SQL query to execute
+    /// SQL query to execute, or a script of queries separated by `;`
     #[arg(short, long)]
     query: Option<String>,
+
+    /// File to read the query from, relative paths in it are resolved against its directory
+    #[arg(short, long, conflicts_with = "query")]
+    file: Option<PathBuf>,
+
+    /// Only output the result of the last query in a script
+    #[arg(long)]
+    last: bool,
+
+    /// Timestamp used for CURRENT_DATE and CURRENT_TIMESTAMP, e.g. '2024-01-31 12:00:00'
+    #[arg(long)]
+    now: Option<String>,
+
+    /// Value for a `:name` placeholder, or for the next `?` when given without a name. Can be repeated
+    #[arg(long = "param", value_name = "NAME=VALUE")]
+    params: Vec<String>,
+
+    /// Field delimiter of text files, e.g. ';' or '\t', by default it depends on the extension
+    #[arg(long, value_parser = parse_character)]
+    delimiter: Option<u8>,
+
+    /// Quote character of text files
+    #[arg(long, value_parser = parse_character)]
+    quote: Option<u8>,
+
+    /// Escape character of text files, by default quotes are escaped by doubling them
+    #[arg(long, value_parser = parse_character)]
+    escape: Option<u8>,
+
+    /// Lines in text files that start with this character are skipped
+    #[arg(long, value_parser = parse_character)]
+    comment: Option<u8>,
+
+    /// Do not guess the delimiter, quote and header of text files, the first line is the header
+    #[arg(long)]
+    no_sniff: bool,
+
+    /// Files and sheets have no header row, columns are named c1, c2, ... in text files and
+    /// A, B, ... in sheets
+    #[arg(long)]
+    no_header: bool,
+
+    /// The 1-based row that holds the header, rows above it are skipped
+    #[arg(long, value_name = "ROW", value_parser = clap::value_parser!(u64).range(1..))]
+    header_row: Option<u64>,
+
+    /// Number of rows at the end of files and sheets to skip, e.g. totals
+    #[arg(long, value_name = "ROWS")]
+    skip_footer: Option<usize>,
+}
+
+fn parse_character(s: &str) -> Result<u8, String> {
+    Dialect::parse_character(s).ok_or_else(|| "expected a single character".to_string())
 }
 
 fn main() {
-    let mut optional_query_string = Args::parse().query;
+    let args = Args::parse();
+    let mut optional_query_string = args.query;
+
+    let now = match args.now {
+        Some(now) => match Timestamp::parse(&now) {
+            Some(now) => now,
+            None => {
+                eprintln!(
+                    "{} invalid timestamp '{}' for {}",
+                    "error:".red().bold(),
+                    now,
+                    "--now".yellow()
+                );
+                exit(1);
+            }
+        },
+        None => Timestamp::now(),
+    };
+
+    let mut directory = None;
+    if let Some(file) = &args.file {
+        match fs::read_to_string(file) {
+            Ok(q) => optional_query_string = Some(q),
+            Err(e) => {
+                eprintln!(
+                    "{} could not read {}: {}",
+                    "error:".red().bold(),
+                    file.display().to_string().bold(),
+                    e
+                );
+                exit(1);
+            }
+        }
+        directory = file.parent().map(|p| p.to_path_buf());
+    }
 
     // read from stdin if no query string is given
     if optional_query_string.is_none() && !atty::is(atty::Stream::Stdin) {
@@ -34,53 +128,61 @@ fn main() {
 
     match optional_query_string {
         Some(query_string) => {
-            let parsed = parse_query(&query_string);
-            let query = match parsed {
-                Ok((_, query)) => {
-                    query
-                }
+            let script = strip_comments(&query_string);
+            let parsed = parse_script(&script);
+            let queries = match parsed {
+                Ok((_, queries)) => queries,
                 Err(e) => {
                     eprintln!("{}", e);
                     exit(1);
                 }
             };
 
+            let mut parameters = Parameters::new(&queries);
+            for param in &args.params {
+                if let Err(e) = parameters.bind(param) {
+                    eprintln!("{} {} for {}", "error:".red().bold(), e, "--param".yellow());
+                    exit(1);
+                }
+            }
 
-            match FileType::parse_to_filetype(query.file.path.split(".").last()) {
-                Some(filetype) => {
-                    if matches!(&filetype, FileType::MultiSheetFiletype(_))
-                        && query.file.sheet.is_none()
-                    {
-                        eprintln!(
-                            "{} a sheet is required for {} files",
-                            "error:".red().bold(),
-                            filetype.to_string().bold()
-                        );
-                        exit(1);
-                    }
-
-                    let mut executor = get_executor(&query.file.path, filetype);
-                    let data = executor.execute_query(&query);
+            // every query's result is written as its own JSON document
+            let dialect = Dialect {
+                delimiter: args.delimiter,
+                quote: args.quote,
+                escape: args.escape,
+                comment: args.comment,
+                sniff: args.no_sniff.then_some(false),
+            };
+            let layout = Layout {
+                header: args.no_header.then_some(false),
+                header_row: args.header_row.map(|row| row as usize),
+                skip_footer: args.skip_footer,
+            };
+            let context = Context::new(now, parameters, directory, dialect, layout);
+            let queries = if args.last {
+                &queries[queries.len() - 1..]
+            } else {
+                &queries[..]
+            };
+            for query in queries {
+                let rows = execute(query, &context).rows.collect::<Vec<Row>>();
 
-                    match data {
-                        Ok(data) => println!("{}", data),
-                        Err(e) => {
-                            eprintln!("{} {}", "error:".red().bold(), e);
-                            exit(1);
-                        }
+                match serde_json::to_string_pretty(&rows) {
+                    Ok(data) => println!("{}", data),
+                    Err(e) => {
+                        eprintln!("{} {}", "error:".red().bold(), e);
+                        exit(1);
                     }
                 }
-                None => {
-                    eprintln!("{} unsupported filetype", "error:".red().bold());
-                    exit(1)
-                }
             }
         }
         None => {
             eprintln!(
-                "{} missing {} argument",
+                "{} missing {} or {} argument",
                 "error:".red().bold(),
-                "\'--query <QUERY>\'".yellow()
+                "\'--query <QUERY>\'".yellow(),
+                "\'--file <FILE>\'".yellow()
             );
             exit(1);
         }
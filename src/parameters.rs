@@ -0,0 +1,149 @@
+use std::{collections::HashMap, process::exit};
+
+use colored::Colorize;
+
+use crate::{
+    executor::str_to_json_value,
+    parser::{CompoundQuery, Parameter},
+};
+
+/// Values bound to the placeholders of a query with `--param`
+#[derive(Debug, Clone, Default)]
+pub struct Parameters {
+    /// keyed by name, or by the 1-based position of a `?` placeholder
+    values: HashMap<String, serde_json::Value>,
+    /// the length of the query after each `?` placeholder, in the order they appear
+    positions: Vec<usize>,
+    next_position: usize,
+}
+
+impl Parameters {
+    pub fn new(queries: &[CompoundQuery]) -> Self {
+        let mut positions = queries
+            .iter()
+            .flat_map(|query| query.placeholders())
+            .collect::<Vec<usize>>();
+        // a placeholder earlier in the script has more of the script after it
+        positions.sort_unstable_by(|a, b| b.cmp(a));
+
+        Self {
+            values: HashMap::new(),
+            positions,
+            next_position: 0,
+        }
+    }
+
+    /// Binds `name=value`, where a number as the name refers to the nth `?`. A value without
+    /// a name binds the next `?`.
+    pub fn bind(&mut self, param: &str) -> Result<(), String> {
+        let (name, value) = match param.split_once('=') {
+            Some((name, value)) => {
+                let name = name.trim().trim_start_matches(':');
+                if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return Err(format!("invalid parameter name '{}'", name));
+                }
+                (name.to_string(), value)
+            }
+            None => {
+                self.next_position += 1;
+                (self.next_position.to_string(), param)
+            }
+        };
+
+        self.values.insert(name, str_to_json_value(value));
+        Ok(())
+    }
+
+    pub fn get(&self, parameter: &Parameter) -> &serde_json::Value {
+        let (name, placeholder) = match parameter {
+            Parameter::Positional(position) => {
+                let n = self
+                    .positions
+                    .iter()
+                    .position(|p| p == position)
+                    .map_or(0, |i| i + 1);
+                (n.to_string(), format!("? #{}", n))
+            }
+            Parameter::Named(name) => (name.to_string(), format!(":{}", name)),
+        };
+
+        match self.values.get(&name) {
+            Some(value) => value,
+            None => {
+                eprintln!(
+                    "{} no value for parameter {}, set one with {}",
+                    "error:".red().bold(),
+                    placeholder.bold(),
+                    format!("--param {}=<VALUE>", name).yellow()
+                );
+                exit(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_script, strip_comments};
+
+    /// Binds 1, 2, 3, ... in order and checks that every `?` gets the number of its place in
+    /// the script, wherever the parser put it
+    fn check_numbering(script: &str) {
+        let script = strip_comments(script);
+        let (_, queries) = parse_script(&script).unwrap();
+
+        let expected = script
+            .match_indices('?')
+            .map(|(i, _)| script.len() - i - 1)
+            .collect::<Vec<usize>>();
+        let mut found = queries
+            .iter()
+            .flat_map(|query| query.placeholders())
+            .collect::<Vec<usize>>();
+        found.sort_unstable_by(|a, b| b.cmp(a));
+        assert_eq!(found, expected);
+
+        let mut parameters = Parameters::new(&queries);
+        for n in 1..=expected.len() {
+            parameters.bind(&n.to_string()).unwrap();
+        }
+        for (n, position) in expected.into_iter().enumerate() {
+            assert_eq!(
+                parameters.get(&Parameter::Positional(position)),
+                &serde_json::Value::from(n + 1)
+            );
+        }
+    }
+
+    #[test]
+    fn placeholders_across_statements() {
+        check_numbering(
+            "SELECT * FROM a.csv WHERE x = ? AND y > ?; SELECT * FROM b.csv WHERE z = ?",
+        );
+        check_numbering("SELECT * FROM a.csv WHERE x = ? UNION SELECT * FROM b.csv WHERE x = ?");
+    }
+
+    #[test]
+    fn placeholders_in_subqueries() {
+        check_numbering(
+            "SELECT * FROM (SELECT * FROM a.csv WHERE x = ?) WHERE y IN (SELECT y FROM b.csv WHERE z > ?) AND w < ?",
+        );
+        check_numbering(
+            "WITH t AS (SELECT * FROM a.csv WHERE x = ?) SELECT * FROM t WHERE y > (SELECT MAX(y) AS m FROM b.csv WHERE z = ?)",
+        );
+    }
+
+    #[test]
+    fn placeholders_in_filter_and_qualify() {
+        check_numbering("SELECT COUNT(*) FILTER (WHERE x = ?) AS n FROM a.csv WHERE y = ?");
+        check_numbering(
+            "SELECT * FROM a.csv WHERE x = ? QUALIFY ROW_NUMBER() OVER (PARTITION BY g ORDER BY id) <= ?",
+        );
+    }
+
+    #[test]
+    fn placeholders_in_comments_do_not_count() {
+        check_numbering("SELECT * FROM a.csv -- x = ?\nWHERE x = ? /* and y = ? */ AND y = ?");
+    }
+}
@@ -1,27 +1,405 @@
-use std::process::exit;
+use std::{cell::OnceCell, collections::HashSet};
 
-use colored::Colorize;
 use nom::{
     branch::alt,
-    bytes::complete::{tag, tag_no_case, take_till, take_until},
+    bytes::complete::{tag, tag_no_case, take_till1, take_until, take_while1},
     character::complete::{alphanumeric1, multispace0, multispace1},
-    combinator::{opt, verify},
-    multi::separated_list1,
-    sequence::{delimited, tuple},
+    character::complete::{digit1, satisfy},
+    combinator::{
+        all_consuming, consumed, cut, map, map_opt, map_res, not, opt, recognize, value, verify,
+    },
+    multi::{many0, many1, separated_list1},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     IResult,
 };
 
+use crate::{
+    filetypes::{column_index, CellRange, Dialect, FileType, Layout},
+    temporal::{Interval, TemporalBase, TemporalExpression, Timestamp},
+};
+
 #[derive(Debug)]
 pub struct FileInfo<'a> {
     pub path: &'a str,
     pub sheet: Option<&'a str>,
+    /// only these cells of the sheet are read, e.g. `SHEET Sheet1!B3:F200`
+    pub range: Option<CellRange>,
+    /// an Excel table or named range, read instead of a sheet
+    pub table: Option<&'a str>,
+    pub dialect: Dialect,
+    pub layout: Layout,
+}
+
+#[derive(Debug)]
+pub enum TableSource<'a> {
+    File(FileInfo<'a>),
+    Subquery(Box<CompoundQuery<'a>>),
 }
 
 #[derive(Debug)]
 pub struct Query<'a> {
+    pub columns: Vec<SelectItem<'a>>,
+    pub from: TableSource<'a>,
+    pub sample: Option<Sample>,
+    pub reshape: Option<Reshape<'a>>,
+    pub conditions: Option<LogicalExpression<'a>>,
+    pub group_by: GroupBy<'a>,
+    pub qualify: Option<Qualify<'a>>,
+}
+
+/// `QUALIFY ROW_NUMBER() OVER (...) = 1`, filters rows on the result of window functions
+#[derive(Debug)]
+pub struct Qualify<'a> {
+    /// window functions written inside the conditions, each named by its own text
+    pub windows: Vec<WindowFunction<'a>>,
+    pub conditions: LogicalExpression<'a>,
+}
+
+/// The grouping sets of a GROUP BY clause, `GROUP BY a, b` is the single set `(a, b)`
+/// while `GROUP BY ROLLUP(a, b)` is the sets `(a, b)`, `(a)` and `()`
+#[derive(Debug, Default)]
+pub struct GroupBy<'a> {
+    /// every column that appears in any of the sets
+    pub columns: Vec<&'a str>,
+    pub sets: Vec<Vec<&'a str>>,
+}
+
+/// `TABLESAMPLE BERNOULLI(5)` or `USING SAMPLE 1000 ROWS`, with an optional `REPEATABLE(seed)`
+#[derive(Debug)]
+pub struct Sample {
+    pub method: SampleMethod,
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum SampleMethod {
+    /// keeps every row with the given probability, in percent
+    Bernoulli(f64),
+    /// keeps this many rows, or every row when there are fewer
+    Reservoir(usize),
+}
+
+/// `PIVOT (SUM(amount) FOR month IN ('Jan', 'Feb' AS february))`, turns the values of a
+/// column into columns, grouping by every other column that the aggregate does not read
+#[derive(Debug)]
+pub struct Pivot<'a> {
+    pub aggregate: AggregateFunction<'a>,
+    pub column: &'a str,
+    /// each value with the name of the column it becomes
+    pub values: Vec<(&'a str, &'a str)>,
+}
+
+/// `UNPIVOT (amount FOR month IN (Jan, Feb))`, turns columns into a name and a value column
+#[derive(Debug)]
+pub struct Unpivot<'a> {
+    pub value: &'a str,
+    pub name: &'a str,
     pub columns: Vec<&'a str>,
-    pub file: FileInfo<'a>,
-    pub conditions: Option<LogicalExpression>,
+}
+
+/// Changes the shape of the table in FROM before the rest of the query sees it
+#[derive(Debug)]
+pub enum Reshape<'a> {
+    Pivot(Pivot<'a>),
+    Unpivot(Unpivot<'a>),
+}
+
+#[derive(Debug, Clone)]
+pub enum SetOperator {
+    Union,
+    UnionAll,
+    Intersect,
+    Except,
+}
+
+#[derive(Debug)]
+pub struct SetOperation<'a> {
+    pub left: Box<CompoundQuery<'a>>,
+    pub right: Box<CompoundQuery<'a>>,
+    pub operator: SetOperator,
+}
+
+/// `name AS (SELECT ...)` in a WITH clause
+#[derive(Debug)]
+pub struct CommonTableExpression<'a> {
+    pub name: &'a str,
+    pub query: Box<CompoundQuery<'a>>,
+}
+
+#[derive(Debug)]
+pub struct With<'a> {
+    pub ctes: Vec<CommonTableExpression<'a>>,
+    pub query: Box<CompoundQuery<'a>>,
+}
+
+/// A single SELECT or several of them combined with UNION, INTERSECT or EXCEPT,
+/// optionally preceded by a WITH clause
+#[derive(Debug)]
+pub enum CompoundQuery<'a> {
+    Query(Box<Query<'a>>),
+    SetOperation(SetOperation<'a>),
+    With(With<'a>),
+}
+
+impl CompoundQuery<'_> {
+    /// The positions of the `?` placeholders anywhere in the query, including subqueries
+    pub fn placeholders(&self) -> Vec<usize> {
+        let mut positions = vec![];
+        self.collect_placeholders(&mut positions);
+        positions
+    }
+
+    fn collect_placeholders(&self, positions: &mut Vec<usize>) {
+        match self {
+            CompoundQuery::Query(query) => {
+                if let TableSource::Subquery(subquery) = &query.from {
+                    subquery.collect_placeholders(positions);
+                }
+
+                let filters = query.columns.iter().filter_map(|column| match column {
+                    SelectItem::Aggregate(aggregate) => aggregate.filter.as_ref(),
+                    _ => None,
+                });
+                let pivot = match &query.reshape {
+                    Some(Reshape::Pivot(pivot)) => pivot.aggregate.filter.as_ref(),
+                    _ => None,
+                };
+                let qualify = query.qualify.as_ref().map(|qualify| &qualify.conditions);
+
+                for conditions in filters
+                    .chain(pivot)
+                    .chain(query.conditions.as_ref())
+                    .chain(qualify)
+                {
+                    conditions.collect_placeholders(positions);
+                }
+            }
+            CompoundQuery::SetOperation(operation) => {
+                operation.left.collect_placeholders(positions);
+                operation.right.collect_placeholders(positions);
+            }
+            CompoundQuery::With(with) => {
+                for cte in &with.ctes {
+                    cte.query.collect_placeholders(positions);
+                }
+                with.query.collect_placeholders(positions);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SelectItem<'a> {
+    Wildcard,
+    Column(&'a str),
+    Window(WindowFunction<'a>),
+    Aggregate(AggregateFunction<'a>),
+}
+
+#[derive(Debug)]
+pub enum AggregateFunctionKind<'a> {
+    /// `COUNT(*)` when no column is given
+    Count(Option<&'a str>),
+    Sum(&'a str),
+    Avg(&'a str),
+    Min(&'a str),
+    Max(&'a str),
+    Median(&'a str),
+    /// `PERCENTILE_CONT(0.9) WITHIN GROUP (ORDER BY column)`
+    PercentileCont(f64, &'a str),
+    PercentileDisc(f64, &'a str),
+    StddevSamp(&'a str),
+    StddevPop(&'a str),
+    VarSamp(&'a str),
+    VarPop(&'a str),
+    Corr(&'a str, &'a str),
+    /// `ARRAY_AGG(column [ORDER BY ...])`
+    ArrayAgg(&'a str, Vec<OrderBy<'a>>),
+    /// `STRING_AGG(column, separator [ORDER BY ...])`
+    StringAgg(&'a str, &'a str, Vec<OrderBy<'a>>),
+    /// `GROUPING(a, b)`, a bit mask of the arguments that are not in the current grouping set
+    Grouping(Vec<&'a str>),
+}
+
+#[derive(Debug)]
+pub struct AggregateFunction<'a> {
+    pub function: AggregateFunctionKind<'a>,
+    /// `FILTER (WHERE ...)`, only the rows that match are aggregated
+    pub filter: Option<LogicalExpression<'a>>,
+    pub alias: Option<&'a str>,
+}
+
+#[derive(Debug, Clone)]
+pub enum WindowFunctionKind<'a> {
+    RowNumber,
+    Rank,
+    DenseRank,
+    Lag(Offset<'a>),
+    Lead(Offset<'a>),
+    FirstValue(&'a str),
+    Sum(&'a str),
+    Avg(&'a str),
+}
+
+/// Arguments of LAG and LEAD, e.g. `LAG(amount, 2, 0)`
+#[derive(Debug, Clone)]
+pub struct Offset<'a> {
+    pub column: &'a str,
+    pub offset: usize,
+    pub default: Option<&'a str>,
+}
+
+#[derive(Debug)]
+pub struct OrderBy<'a> {
+    pub column: &'a str,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum FrameBound {
+    UnboundedPreceding,
+    Preceding(usize),
+    CurrentRow,
+    Following(usize),
+    UnboundedFollowing,
+}
+
+/// `ROWS BETWEEN <start> AND <end>`
+#[derive(Debug)]
+pub struct WindowFrame {
+    pub start: FrameBound,
+    pub end: FrameBound,
+}
+
+#[derive(Debug)]
+pub struct WindowFunction<'a> {
+    pub function: WindowFunctionKind<'a>,
+    pub partition_by: Vec<&'a str>,
+    pub order_by: Vec<OrderBy<'a>>,
+    pub frame: Option<WindowFrame>,
+    pub alias: Option<&'a str>,
+}
+
+impl SelectItem<'_> {
+    /// Name of the column this item produces in the output
+    pub fn name(&self) -> &str {
+        match self {
+            SelectItem::Wildcard => "*",
+            SelectItem::Column(column) => column,
+            SelectItem::Window(window) => window.name(),
+            SelectItem::Aggregate(aggregate) => aggregate.name(),
+        }
+    }
+}
+
+impl AggregateFunction<'_> {
+    pub fn name(&self) -> &str {
+        if let Some(alias) = self.alias {
+            return alias;
+        }
+
+        match self.function {
+            AggregateFunctionKind::Count(_) => "count",
+            AggregateFunctionKind::Sum(_) => "sum",
+            AggregateFunctionKind::Avg(_) => "avg",
+            AggregateFunctionKind::Min(_) => "min",
+            AggregateFunctionKind::Max(_) => "max",
+            AggregateFunctionKind::Median(_) => "median",
+            AggregateFunctionKind::PercentileCont(_, _) => "percentile_cont",
+            AggregateFunctionKind::PercentileDisc(_, _) => "percentile_disc",
+            AggregateFunctionKind::StddevSamp(_) => "stddev_samp",
+            AggregateFunctionKind::StddevPop(_) => "stddev_pop",
+            AggregateFunctionKind::VarSamp(_) => "var_samp",
+            AggregateFunctionKind::VarPop(_) => "var_pop",
+            AggregateFunctionKind::Corr(_, _) => "corr",
+            AggregateFunctionKind::ArrayAgg(_, _) => "array_agg",
+            AggregateFunctionKind::StringAgg(_, _, _) => "string_agg",
+            AggregateFunctionKind::Grouping(_) => "grouping",
+        }
+    }
+
+    /// Every input column the aggregate reads
+    pub fn referenced_columns(&self) -> Vec<&str> {
+        let mut columns: Vec<&str> = match &self.function {
+            AggregateFunctionKind::Count(column) => column.iter().copied().collect(),
+            AggregateFunctionKind::Corr(y, x) => vec![*y, *x],
+            AggregateFunctionKind::Grouping(columns) => columns.clone(),
+            AggregateFunctionKind::ArrayAgg(column, order_by)
+            | AggregateFunctionKind::StringAgg(column, _, order_by) => {
+                let mut columns = vec![*column];
+                columns.extend(order_by.iter().map(|o| o.column));
+                columns
+            }
+            AggregateFunctionKind::Sum(column)
+            | AggregateFunctionKind::Avg(column)
+            | AggregateFunctionKind::Min(column)
+            | AggregateFunctionKind::Max(column)
+            | AggregateFunctionKind::Median(column)
+            | AggregateFunctionKind::PercentileCont(_, column)
+            | AggregateFunctionKind::PercentileDisc(_, column)
+            | AggregateFunctionKind::StddevSamp(column)
+            | AggregateFunctionKind::StddevPop(column)
+            | AggregateFunctionKind::VarSamp(column)
+            | AggregateFunctionKind::VarPop(column) => vec![*column],
+        };
+
+        if let Some(filter) = &self.filter {
+            columns.extend(filter.referenced_columns());
+        }
+
+        columns
+    }
+}
+
+impl Reshape<'_> {
+    /// Every input column the reshape reads
+    pub fn referenced_columns(&self) -> Vec<&str> {
+        match self {
+            Reshape::Pivot(pivot) => {
+                let mut columns = pivot.aggregate.referenced_columns();
+                columns.push(pivot.column);
+                columns
+            }
+            Reshape::Unpivot(unpivot) => unpivot.columns.clone(),
+        }
+    }
+}
+
+impl WindowFunction<'_> {
+    pub fn name(&self) -> &str {
+        if let Some(alias) = self.alias {
+            return alias;
+        }
+
+        match self.function {
+            WindowFunctionKind::RowNumber => "row_number",
+            WindowFunctionKind::Rank => "rank",
+            WindowFunctionKind::DenseRank => "dense_rank",
+            WindowFunctionKind::Lag(_) => "lag",
+            WindowFunctionKind::Lead(_) => "lead",
+            WindowFunctionKind::FirstValue(_) => "first_value",
+            WindowFunctionKind::Sum(_) => "sum",
+            WindowFunctionKind::Avg(_) => "avg",
+        }
+    }
+
+    /// Every input column the window function reads
+    pub fn referenced_columns(&self) -> Vec<&str> {
+        let mut columns = self.partition_by.clone();
+        columns.extend(self.order_by.iter().map(|o| o.column));
+
+        match &self.function {
+            WindowFunctionKind::Lag(offset) | WindowFunctionKind::Lead(offset) => {
+                columns.push(offset.column)
+            }
+            WindowFunctionKind::FirstValue(column)
+            | WindowFunctionKind::Sum(column)
+            | WindowFunctionKind::Avg(column) => columns.push(column),
+            _ => {}
+        }
+
+        columns
+    }
 }
 
 #[derive(Debug)]
@@ -47,7 +425,19 @@ impl ComparisonOperator {
             _ => None,
         }
     }
+
+    /// The operator that gives the same result with the operands swapped
+    fn mirror(self) -> Self {
+        match self {
+            Self::GreaterThan => Self::LessThan,
+            Self::LessThan => Self::GreaterThan,
+            Self::GreaterThanOrEqual => Self::LessThanOrEqual,
+            Self::LessThanOrEqual => Self::GreaterThanOrEqual,
+            operator => operator,
+        }
+    }
 }
+
 #[derive(Debug)]
 pub enum LogicalOperator {
     And,
@@ -64,98 +454,1131 @@ impl LogicalOperator {
     }
 }
 
+/// A subquery used as a single value, e.g. `amount > (SELECT amount FROM 'b.csv' WHERE id = 1)`.
+/// The result is computed the first time it is needed and reused for every row.
+#[derive(Debug)]
+pub struct ScalarSubquery<'a> {
+    pub query: Box<CompoundQuery<'a>>,
+    pub value: OnceCell<serde_json::Value>,
+}
+
+#[derive(Debug)]
+pub enum PredicateValue<'a> {
+    Literal(String),
+    Temporal(TemporalExpression),
+    Subquery(ScalarSubquery<'a>),
+    Parameter(Parameter<'a>),
+}
+
+/// A placeholder whose value is given on the command line
+#[derive(Debug)]
+pub enum Parameter<'a> {
+    /// `?`, identified by the length of the query after it so that placeholders can be
+    /// numbered in the order they appear
+    Positional(usize),
+    /// `:name`
+    Named(&'a str),
+}
+
 #[derive(Debug)]
-pub struct Predicate {
+pub struct Predicate<'a> {
     pub column: String,
     pub operator: ComparisonOperator,
-    pub value: String,
+    pub value: PredicateValue<'a>,
+    /// the window function when the column is one, named by its own text
+    pub window: Option<Box<WindowFunction<'a>>>,
+}
+
+/// `column [NOT] IN (SELECT ...)`, the subquery is evaluated once and cached in `values`
+#[derive(Debug)]
+pub struct InPredicate<'a> {
+    pub column: String,
+    pub negated: bool,
+    pub query: Box<CompoundQuery<'a>>,
+    pub values: OnceCell<HashSet<String>>,
 }
 
 #[derive(Debug)]
-pub struct Condition {
-    pub left: Box<LogicalExpression>,
-    pub right: Box<LogicalExpression>,
+pub struct Condition<'a> {
+    pub left: Box<LogicalExpression<'a>>,
+    pub right: Box<LogicalExpression<'a>>,
     pub operator: LogicalOperator,
 }
 
 #[derive(Debug)]
-pub enum LogicalExpression {
-    Predicate(Predicate),
-    Condition(Condition),
+pub enum LogicalExpression<'a> {
+    Predicate(Predicate<'a>),
+    In(InPredicate<'a>),
+    Condition(Condition<'a>),
+}
+
+impl<'a> LogicalExpression<'a> {
+    fn collect_placeholders(&self, positions: &mut Vec<usize>) {
+        match self {
+            LogicalExpression::Predicate(predicate) => match &predicate.value {
+                PredicateValue::Parameter(Parameter::Positional(position)) => {
+                    positions.push(*position)
+                }
+                PredicateValue::Subquery(subquery) => {
+                    subquery.query.collect_placeholders(positions)
+                }
+                _ => {}
+            },
+            LogicalExpression::In(predicate) => predicate.query.collect_placeholders(positions),
+            LogicalExpression::Condition(condition) => {
+                condition.left.collect_placeholders(positions);
+                condition.right.collect_placeholders(positions);
+            }
+        }
+    }
+
+    /// Moves the window functions written in the conditions out of them
+    fn take_windows(&mut self, windows: &mut Vec<WindowFunction<'a>>) {
+        match self {
+            LogicalExpression::Predicate(predicate) => {
+                if let Some(window) = predicate.window.take() {
+                    if !windows.iter().any(|w| w.alias == window.alias) {
+                        windows.push(*window);
+                    }
+                }
+            }
+            LogicalExpression::In(_) => {}
+            LogicalExpression::Condition(condition) => {
+                condition.left.take_windows(windows);
+                condition.right.take_windows(windows);
+            }
+        }
+    }
+
+    /// Every input column the conditions read
+    pub fn referenced_columns(&self) -> Vec<&str> {
+        match self {
+            LogicalExpression::Predicate(predicate) => vec![predicate.column.as_str()],
+            LogicalExpression::In(predicate) => vec![predicate.column.as_str()],
+            LogicalExpression::Condition(condition) => {
+                let mut columns = condition.left.referenced_columns();
+                columns.extend(condition.right.referenced_columns());
+                columns
+            }
+        }
+    }
+}
+
+/// Parses a script of queries separated by `;`
+pub fn parse_script(input: &str) -> IResult<&str, Vec<CompoundQuery<'_>>> {
+    let separators = || pair(multispace0, many0(terminated(tag(";"), multispace0)));
+
+    all_consuming(delimited(
+        separators(),
+        separated_list1(
+            many1(delimited(multispace0, tag(";"), multispace0)),
+            parse_query,
+        ),
+        separators(),
+    ))(input)
+}
+
+/// Replaces `--` and `/* */` comments with spaces, so that positions in the query stay the same
+pub fn strip_comments(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    let mut quote = None;
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '-') if chars.peek() == Some(&'-') => {
+                output.push(' ');
+                while let Some(c) = chars.next_if(|c| *c != '\n') {
+                    output.push_str(&" ".repeat(c.len_utf8()));
+                }
+                continue;
+            }
+            (None, '/') if chars.peek() == Some(&'*') => {
+                let mut previous = c;
+                output.push(' ');
+                for c in chars.by_ref() {
+                    output.push_str(&" ".repeat(c.len_utf8()));
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        output.push(c);
+    }
+
+    output
 }
 
-pub fn parse_query(input: &str) -> IResult<&str, Query> {
-    let (remaining, (_, columns, (table, sheet))) =
-        tuple((parse_select, parse_columns, parse_from))(input).unwrap();
+pub fn parse_query(input: &str) -> IResult<&str, CompoundQuery<'_>> {
+    alt((parse_with, parse_compound_query))(input)
+}
 
-    let (remaining, conditions) = parse_where(remaining, &columns)?;
+fn parse_with(input: &str) -> IResult<&str, CompoundQuery<'_>> {
+    let (remaining, (_, ctes, _, query)) = tuple((
+        pair(tag_no_case("WITH"), multispace1),
+        cut(separated_list1(
+            parse_comma,
+            map(
+                tuple((
+                    parse_identifier,
+                    multispace1,
+                    tag_no_case("AS"),
+                    multispace0,
+                    parse_subquery,
+                )),
+                |(name, _, _, _, query)| CommonTableExpression { name, query },
+            ),
+        )),
+        multispace0,
+        cut(parse_compound_query),
+    ))(input)?;
+
+    Ok((
+        remaining,
+        CompoundQuery::With(With {
+            ctes,
+            query: Box::new(query),
+        }),
+    ))
+}
+
+fn parse_compound_query(input: &str) -> IResult<&str, CompoundQuery<'_>> {
+    // INTERSECT binds tighter than UNION and EXCEPT
+    let (remaining, (first, rest)) = tuple((
+        parse_intersection,
+        many0(pair(
+            parse_set_operator(alt((
+                value(
+                    SetOperator::UnionAll,
+                    tuple((tag_no_case("UNION"), multispace1, tag_no_case("ALL"))),
+                ),
+                value(SetOperator::Union, tag_no_case("UNION")),
+                value(SetOperator::Except, tag_no_case("EXCEPT")),
+            ))),
+            cut(parse_intersection),
+        )),
+    ))(input)?;
+
+    Ok((remaining, combine_queries(first, rest)))
+}
+
+fn parse_intersection(input: &str) -> IResult<&str, CompoundQuery<'_>> {
+    let (remaining, (first, rest)) = tuple((
+        map(parse_select_query, |query| {
+            CompoundQuery::Query(Box::new(query))
+        }),
+        many0(pair(
+            parse_set_operator(value(SetOperator::Intersect, tag_no_case("INTERSECT"))),
+            cut(map(parse_select_query, |query| {
+                CompoundQuery::Query(Box::new(query))
+            })),
+        )),
+    ))(input)?;
+
+    Ok((remaining, combine_queries(first, rest)))
+}
+
+fn parse_set_operator<'a, F>(operator: F) -> impl FnMut(&'a str) -> IResult<&'a str, SetOperator>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, SetOperator>,
+{
+    delimited(multispace0, operator, multispace1)
+}
+
+fn combine_queries<'a>(
+    first: CompoundQuery<'a>,
+    rest: Vec<(SetOperator, CompoundQuery<'a>)>,
+) -> CompoundQuery<'a> {
+    rest.into_iter().fold(first, |left, (operator, right)| {
+        CompoundQuery::SetOperation(SetOperation {
+            left: Box::new(left),
+            right: Box::new(right),
+            operator,
+        })
+    })
+}
+
+fn parse_select_query(input: &str) -> IResult<&str, Query<'_>> {
+    let (remaining, (_, columns, from, sample, reshape)) = tuple((
+        parse_select,
+        parse_columns,
+        parse_from,
+        opt(preceded(multispace1, parse_sample)),
+        opt(preceded(multispace1, parse_reshape)),
+    ))(input)?;
+
+    let column_names = columns
+        .iter()
+        .filter_map(|c| match c {
+            SelectItem::Column(column) => Some(*column),
+            _ => None,
+        })
+        .collect::<Vec<&str>>();
+
+    let (remaining, conditions) = parse_where(remaining, &column_names)?;
+    let (remaining, group_by) = parse_group_by(remaining)?;
+    let (remaining, qualify) = parse_qualify(remaining, &column_names)?;
 
     Ok((
         remaining,
         Query {
-            columns: columns.clone(),
-            file: FileInfo { path: table, sheet },
+            columns,
+            from,
+            sample,
+            reshape,
             conditions,
+            group_by: group_by.unwrap_or_default(),
+            qualify,
         },
     ))
 }
 
-fn parse_sheet(input: &str) -> IResult<&str, Option<&str>> {
-    let (remaining, sheet) = opt(tuple((
-        multispace1,
-        tag_no_case("SHEET"),
-        multispace1,
-        parse_until_next_keyword,
-    )))(input)?;
+/// `SHEET name`, optionally followed by a range of cells, e.g. `SHEET 'Sales 2024'!B3:F200`
+fn parse_sheet(input: &str) -> IResult<&str, Option<(&str, Option<CellRange>)>> {
+    opt(preceded(
+        tuple((multispace1, tag_no_case("SHEET"), multispace1)),
+        pair(
+            alt((
+                parse_quoted,
+                take_till1(|c: char| c.is_whitespace() || c == ';' || c == ')' || c == '!'),
+            )),
+            opt(preceded(tag("!"), parse_cell_range)),
+        ),
+    ))(input)
+}
 
-    match sheet {
-        Some((_, _, _, sheet)) => Ok((remaining, Some(sheet))),
-        None => Ok((remaining, None)),
-    }
+/// `TABLE name`, an Excel table or a named range
+fn parse_named_table(input: &str) -> IResult<&str, &str> {
+    preceded(
+        tuple((multispace1, tag_no_case("TABLE"), multispace1)),
+        alt((parse_quoted, parse_until_next_keyword)),
+    )(input)
+}
+
+/// The sheet and cells a named range refers to, e.g. `'Sales 2024'!$B$3:$F$200`
+pub fn parse_reference(reference: &str) -> Option<(&str, CellRange)> {
+    let (_, (sheet, _, range)) = all_consuming(tuple((
+        alt((parse_quoted, take_till1(|c: char| c == '!'))),
+        tag("!"),
+        parse_cell_range,
+    )))(reference.trim_start_matches('='))
+    .ok()?;
+
+    Some((sheet, range))
+}
+
+/// `B3:F200`, absolute references like `$B$3:$F$200` are accepted too
+fn parse_cell_range(input: &str) -> IResult<&str, CellRange> {
+    map(
+        verify(
+            separated_pair(parse_cell, tag(":"), parse_cell),
+            |(start, end)| start.0 <= end.0 && start.1 <= end.1,
+        ),
+        |(start, end)| CellRange { start, end },
+    )(input)
+}
+
+fn parse_cell(input: &str) -> IResult<&str, (u32, u32)> {
+    map_opt(
+        tuple((
+            opt(tag("$")),
+            take_while1(|c: char| c.is_ascii_alphabetic()),
+            opt(tag("$")),
+            parse_usize,
+        )),
+        |(_, column, _, row)| {
+            let column = u32::try_from(column_index(column)?).ok()?;
+            let row = u32::try_from(row.checked_sub(1)?).ok()?;
+            Some((row, column))
+        },
+    )(input)
 }
 
 fn parse_select(input: &str) -> IResult<&str, (&str, &str)> {
     tuple((tag_no_case("SELECT"), multispace1))(input)
 }
 
-fn parse_from(input: &str) -> IResult<&str, (&str, Option<&str>)> {
-    let (remaining, (_, _, _, table)) = tuple((
-        multispace1,
-        tag_no_case("FROM"),
-        multispace1,
-        parse_until_next_keyword,
-    ))(input)?;
+fn parse_from(input: &str) -> IResult<&str, TableSource<'_>> {
+    preceded(
+        tuple((multispace1, tag_no_case("FROM"), multispace1)),
+        alt((
+            map(
+                terminated(parse_subquery, opt(parse_table_alias)),
+                TableSource::Subquery,
+            ),
+            map(parse_file, TableSource::File),
+        )),
+    )(input)
+}
 
-    if table.split(".").last().unwrap() == "csv" {
+fn parse_file(input: &str) -> IResult<&str, FileInfo<'_>> {
+    let (remaining, path) = alt((parse_quoted, parse_until_next_keyword))(input)?;
+
+    if let Some(FileType::SingleSheetFileType(filetype)) =
+        FileType::parse_to_filetype(path.rsplit('.').next())
+    {
         verify(parse_sheet, |s| s.is_none())(remaining)?;
+        // JSON has no delimiters or header rows, so the options for them are not accepted
+        let (remaining, (dialect, layout)) = if filetype.is_json() {
+            (remaining, (None, Layout::default()))
+        } else {
+            pair(opt(parse_dialect), parse_layout)(remaining)?
+        };
 
-        Ok((remaining, (table, None)))
+        Ok((
+            remaining,
+            FileInfo {
+                path,
+                sheet: None,
+                range: None,
+                table: None,
+                dialect: dialect.unwrap_or_default(),
+                layout,
+            },
+        ))
     } else {
-        let (remaining, sheet) = parse_sheet(remaining)?;
-        Ok((remaining, (table, sheet)))
+        let (remaining, table) = opt(parse_named_table)(remaining)?;
+        let (remaining, sheet) = match table {
+            Some(_) => (remaining, None),
+            None => parse_sheet(remaining)?,
+        };
+        let (remaining, layout) = parse_layout(remaining)?;
+        let (sheet, range) = match sheet {
+            Some((sheet, range)) => (Some(sheet), range),
+            None => (None, None),
+        };
+
+        Ok((
+            remaining,
+            FileInfo {
+                path,
+                sheet,
+                range,
+                table,
+                dialect: Dialect::default(),
+                layout,
+            },
+        ))
     }
 }
 
+/// `[HEADER ROW n] [NO HEADER] [SKIP FOOTER n]` after a file or sheet
+fn parse_layout(input: &str) -> IResult<&str, Layout> {
+    map(
+        tuple((
+            opt(preceded(
+                tuple((
+                    multispace1,
+                    tag_no_case("HEADER"),
+                    multispace1,
+                    tag_no_case("ROW"),
+                    multispace1,
+                )),
+                verify(parse_usize, |row| *row >= 1),
+            )),
+            opt(tuple((
+                multispace1,
+                tag_no_case("NO"),
+                multispace1,
+                tag_no_case("HEADER"),
+            ))),
+            opt(preceded(
+                tuple((
+                    multispace1,
+                    tag_no_case("SKIP"),
+                    multispace1,
+                    tag_no_case("FOOTER"),
+                    multispace1,
+                )),
+                parse_usize,
+            )),
+        )),
+        |(header_row, no_header, skip_footer)| Layout {
+            header: no_header.map(|_| false),
+            header_row,
+            skip_footer,
+        },
+    )(input)
+}
+
+/// `WITH (delimiter '|', quote '"')` after a text file
+fn parse_dialect(input: &str) -> IResult<&str, Dialect> {
+    let option = separated_pair(
+        alt((
+            tag_no_case("delimiter"),
+            tag_no_case("quote"),
+            tag_no_case("escape"),
+            tag_no_case("comment"),
+        )),
+        tuple((multispace0, opt(tag("=")), multispace0)),
+        map_opt(parse_quoted, Dialect::parse_character),
+    );
+
+    map(
+        preceded(
+            pair(multispace1, tag_no_case("WITH")),
+            parse_arguments(separated_list1(parse_comma, option)),
+        ),
+        |options| {
+            let mut dialect = Dialect::default();
+            for (name, character) in options {
+                match name.to_lowercase().as_str() {
+                    "delimiter" => dialect.delimiter = Some(character),
+                    "quote" => dialect.quote = Some(character),
+                    "escape" => dialect.escape = Some(character),
+                    _ => dialect.comment = Some(character),
+                }
+            }
+            dialect
+        },
+    )(input)
+}
+
+/// `(SELECT ...)`
+fn parse_subquery(input: &str) -> IResult<&str, Box<CompoundQuery<'_>>> {
+    map(
+        delimited(
+            pair(tag("("), multispace0),
+            parse_query,
+            pair(multispace0, tag(")")),
+        ),
+        Box::new,
+    )(input)
+}
+
+/// `[AS] alias` after a subquery in FROM, columns are never qualified so the alias is unused
+fn parse_table_alias(input: &str) -> IResult<&str, &str> {
+    preceded(
+        tuple((multispace1, opt(pair(tag_no_case("AS"), multispace1)))),
+        verify(parse_identifier, |alias: &str| !is_keyword(alias)),
+    )(input)
+}
+
+fn parse_percentage(input: &str) -> IResult<&str, f64> {
+    verify(
+        map_res(
+            take_while1(|c: char| c.is_ascii_digit() || c == '.'),
+            |s: &str| s.parse::<f64>(),
+        ),
+        |percentage| (0.0..=100.0).contains(percentage),
+    )(input)
+}
+
+fn parse_sample(input: &str) -> IResult<&str, Sample> {
+    map(
+        pair(
+            alt((
+                preceded(
+                    tuple((
+                        tag_no_case("TABLESAMPLE"),
+                        multispace1,
+                        tag_no_case("BERNOULLI"),
+                    )),
+                    map(parse_arguments(parse_percentage), SampleMethod::Bernoulli),
+                ),
+                preceded(
+                    tuple((
+                        tag_no_case("USING"),
+                        multispace1,
+                        tag_no_case("SAMPLE"),
+                        multispace1,
+                    )),
+                    alt((
+                        map(
+                            terminated(parse_usize, pair(multispace1, tag_no_case("ROWS"))),
+                            SampleMethod::Reservoir,
+                        ),
+                        map(
+                            terminated(
+                                parse_percentage,
+                                alt((
+                                    preceded(multispace0, tag("%")),
+                                    preceded(multispace1, tag_no_case("PERCENT")),
+                                )),
+                            ),
+                            SampleMethod::Bernoulli,
+                        ),
+                    )),
+                ),
+            )),
+            opt(preceded(
+                pair(multispace1, tag_no_case("REPEATABLE")),
+                parse_arguments(map_res(digit1, |s: &str| s.parse::<u64>())),
+            )),
+        ),
+        |(method, seed)| Sample { method, seed },
+    )(input)
+}
+
+fn parse_reshape(input: &str) -> IResult<&str, Reshape<'_>> {
+    let for_in = |input| {
+        delimited(
+            tuple((multispace1, tag_no_case("FOR"), multispace1)),
+            parse_identifier,
+            tuple((multispace1, tag_no_case("IN"))),
+        )(input)
+    };
+
+    alt((
+        map(
+            preceded(
+                tag_no_case("PIVOT"),
+                parse_arguments(tuple((
+                    verify(parse_aggregate_function, |aggregate| {
+                        !matches!(aggregate.function, AggregateFunctionKind::Grouping(_))
+                    }),
+                    for_in,
+                    parse_arguments(separated_list1(
+                        parse_comma,
+                        map(pair(parse_literal, opt(parse_alias)), |(value, alias)| {
+                            (value, alias.unwrap_or(value))
+                        }),
+                    )),
+                ))),
+            ),
+            |(aggregate, column, values)| {
+                Reshape::Pivot(Pivot {
+                    aggregate,
+                    column,
+                    values,
+                })
+            },
+        ),
+        map(
+            preceded(
+                tag_no_case("UNPIVOT"),
+                parse_arguments(tuple((
+                    parse_identifier,
+                    for_in,
+                    parse_arguments(separated_list1(parse_comma, parse_identifier)),
+                ))),
+            ),
+            |(value, name, columns)| {
+                Reshape::Unpivot(Unpivot {
+                    value,
+                    name,
+                    columns,
+                })
+            },
+        ),
+    ))(input)
+}
+
+fn is_keyword(s: &str) -> bool {
+    [
+        "WHERE",
+        "UNION",
+        "INTERSECT",
+        "EXCEPT",
+        "GROUP",
+        "PIVOT",
+        "UNPIVOT",
+        "QUALIFY",
+        "TABLESAMPLE",
+        "USING",
+    ]
+    .iter()
+    .any(|keyword| keyword.eq_ignore_ascii_case(s))
+}
+
 fn parse_until_next_keyword(input: &str) -> IResult<&str, &str> {
-    alt((take_until(" "), take_until(";"), take_till(|c| c == '\0')))(input)
+    take_till1(|c: char| c.is_whitespace() || c == ';' || c == ')')(input)
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// A column name, which can have dots in it for the flattened objects of a JSON file, `user.name`
+fn parse_identifier(input: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        take_while1(is_identifier_char),
+        many0(pair(tag("."), take_while1(is_identifier_char))),
+    ))(input)
 }
 
-fn parse_columns(input: &str) -> IResult<&str, Vec<&str>> {
+fn parse_columns(input: &str) -> IResult<&str, Vec<SelectItem<'_>>> {
     separated_list1(
+        tuple((multispace0, tag(","), multispace0)),
+        alt((
+            map(parse_window_function, SelectItem::Window),
+            map(parse_aggregate_function, SelectItem::Aggregate),
+            map(parse_identifier, SelectItem::Column),
+            map(tag("*"), |_| SelectItem::Wildcard),
+        )),
+    )(input)
+}
+
+fn parse_usize(input: &str) -> IResult<&str, usize> {
+    map_res(digit1, |s: &str| s.parse::<usize>())(input)
+}
+
+fn parse_literal(input: &str) -> IResult<&str, &str> {
+    alt((
+        parse_quoted,
+        take_while1(|c: char| c.is_alphanumeric() || c == '.' || c == '-'),
+    ))(input)
+}
+
+fn parse_comma(input: &str) -> IResult<&str, &str> {
+    delimited(multispace0, tag(","), multispace0)(input)
+}
+
+/// Parses the parenthesized arguments of a function, e.g. `(amount)`
+fn parse_arguments<'a, O, F>(parser: F) -> impl FnMut(&'a str) -> IResult<&'a str, O>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, O>,
+{
+    delimited(
+        tuple((multispace0, tag("("), multispace0)),
+        parser,
+        tuple((multispace0, tag(")"))),
+    )
+}
+
+fn parse_offset(input: &str) -> IResult<&str, Offset<'_>> {
+    map(
+        parse_arguments(tuple((
+            parse_identifier,
+            opt(preceded(parse_comma, parse_usize)),
+            opt(preceded(parse_comma, parse_literal)),
+        ))),
+        |(column, offset, default)| Offset {
+            column,
+            offset: offset.unwrap_or(1),
+            default,
+        },
+    )(input)
+}
+
+fn parse_window_function_kind(input: &str) -> IResult<&str, WindowFunctionKind<'_>> {
+    let no_arguments = || parse_arguments(multispace0);
+
+    alt((
+        value(
+            WindowFunctionKind::RowNumber,
+            pair(tag_no_case("ROW_NUMBER"), no_arguments()),
+        ),
+        value(
+            WindowFunctionKind::DenseRank,
+            pair(tag_no_case("DENSE_RANK"), no_arguments()),
+        ),
+        value(
+            WindowFunctionKind::Rank,
+            pair(tag_no_case("RANK"), no_arguments()),
+        ),
+        map(
+            preceded(tag_no_case("LAG"), parse_offset),
+            WindowFunctionKind::Lag,
+        ),
+        map(
+            preceded(tag_no_case("LEAD"), parse_offset),
+            WindowFunctionKind::Lead,
+        ),
+        map(
+            preceded(
+                tag_no_case("FIRST_VALUE"),
+                parse_arguments(parse_identifier),
+            ),
+            WindowFunctionKind::FirstValue,
+        ),
+        map(
+            preceded(tag_no_case("SUM"), parse_arguments(parse_identifier)),
+            WindowFunctionKind::Sum,
+        ),
+        map(
+            preceded(tag_no_case("AVG"), parse_arguments(parse_identifier)),
+            WindowFunctionKind::Avg,
+        ),
+    ))(input)
+}
+
+fn parse_partition_by(input: &str) -> IResult<&str, Vec<&str>> {
+    preceded(
         tuple((
-            take_until(","),
-            tag(","),
-            take_till(|c: char| c.is_alphanumeric()),
+            tag_no_case("PARTITION"),
+            multispace1,
+            tag_no_case("BY"),
+            multispace1,
         )),
-        alt((alphanumeric1, tag("*"))),
+        separated_list1(parse_comma, parse_identifier),
     )(input)
 }
 
+fn parse_order_by(input: &str) -> IResult<&str, Vec<OrderBy<'_>>> {
+    preceded(
+        tuple((
+            tag_no_case("ORDER"),
+            multispace1,
+            tag_no_case("BY"),
+            multispace1,
+        )),
+        separated_list1(
+            parse_comma,
+            map(
+                pair(
+                    parse_identifier,
+                    opt(preceded(
+                        multispace1,
+                        alt((tag_no_case("ASC"), tag_no_case("DESC"))),
+                    )),
+                ),
+                |(column, direction)| OrderBy {
+                    column,
+                    descending: direction.is_some_and(|d| d.eq_ignore_ascii_case("DESC")),
+                },
+            ),
+        ),
+    )(input)
+}
+
+fn parse_frame_bound(input: &str) -> IResult<&str, FrameBound> {
+    alt((
+        value(
+            FrameBound::UnboundedPreceding,
+            tuple((
+                tag_no_case("UNBOUNDED"),
+                multispace1,
+                tag_no_case("PRECEDING"),
+            )),
+        ),
+        value(
+            FrameBound::UnboundedFollowing,
+            tuple((
+                tag_no_case("UNBOUNDED"),
+                multispace1,
+                tag_no_case("FOLLOWING"),
+            )),
+        ),
+        value(
+            FrameBound::CurrentRow,
+            tuple((tag_no_case("CURRENT"), multispace1, tag_no_case("ROW"))),
+        ),
+        map(
+            terminated(parse_usize, pair(multispace1, tag_no_case("PRECEDING"))),
+            FrameBound::Preceding,
+        ),
+        map(
+            terminated(parse_usize, pair(multispace1, tag_no_case("FOLLOWING"))),
+            FrameBound::Following,
+        ),
+    ))(input)
+}
+
+fn parse_window_frame(input: &str) -> IResult<&str, WindowFrame> {
+    preceded(
+        pair(tag_no_case("ROWS"), multispace1),
+        alt((
+            map(
+                tuple((
+                    tag_no_case("BETWEEN"),
+                    multispace1,
+                    parse_frame_bound,
+                    multispace1,
+                    tag_no_case("AND"),
+                    multispace1,
+                    parse_frame_bound,
+                )),
+                |(_, _, start, _, _, _, end)| WindowFrame { start, end },
+            ),
+            // `ROWS <start>` is shorthand for `ROWS BETWEEN <start> AND CURRENT ROW`
+            map(parse_frame_bound, |start| WindowFrame {
+                start,
+                end: FrameBound::CurrentRow,
+            }),
+        )),
+    )(input)
+}
+
+fn parse_window_function(input: &str) -> IResult<&str, WindowFunction<'_>> {
+    let (remaining, (function, _, _, _, _, partition_by, order_by, frame, _, _, alias)) =
+        tuple((
+            parse_window_function_kind,
+            multispace1,
+            tag_no_case("OVER"),
+            multispace0,
+            tag("("),
+            opt(delimited(multispace0, parse_partition_by, multispace0)),
+            opt(delimited(multispace0, parse_order_by, multispace0)),
+            opt(delimited(multispace0, parse_window_frame, multispace0)),
+            multispace0,
+            tag(")"),
+            opt(parse_alias),
+        ))(input)?;
+
+    Ok((
+        remaining,
+        WindowFunction {
+            function,
+            partition_by: partition_by.unwrap_or_default(),
+            order_by: order_by.unwrap_or_default(),
+            frame,
+            alias,
+        },
+    ))
+}
+
+fn parse_alias(input: &str) -> IResult<&str, &str> {
+    preceded(
+        tuple((multispace1, tag_no_case("AS"), multispace1)),
+        parse_identifier,
+    )(input)
+}
+
+fn parse_fraction(input: &str) -> IResult<&str, f64> {
+    verify(
+        map_res(
+            take_while1(|c: char| c.is_ascii_digit() || c == '.'),
+            |s: &str| s.parse::<f64>(),
+        ),
+        |fraction| (0.0..=1.0).contains(fraction),
+    )(input)
+}
+
+/// `(fraction) WITHIN GROUP (ORDER BY column)`
+fn parse_percentile(input: &str) -> IResult<&str, (f64, &str)> {
+    tuple((
+        parse_arguments(parse_fraction),
+        preceded(
+            tuple((
+                multispace1,
+                tag_no_case("WITHIN"),
+                multispace1,
+                tag_no_case("GROUP"),
+            )),
+            parse_arguments(preceded(
+                tuple((
+                    tag_no_case("ORDER"),
+                    multispace1,
+                    tag_no_case("BY"),
+                    multispace1,
+                )),
+                parse_identifier,
+            )),
+        ),
+    ))(input)
+}
+
+fn parse_aggregate_function_kind(input: &str) -> IResult<&str, AggregateFunctionKind<'_>> {
+    let column = || parse_arguments(parse_identifier);
+
+    alt((
+        map(
+            preceded(
+                tag_no_case("COUNT"),
+                parse_arguments(alt((map(tag("*"), |_| None), map(parse_identifier, Some)))),
+            ),
+            AggregateFunctionKind::Count,
+        ),
+        map(
+            preceded(tag_no_case("SUM"), column()),
+            AggregateFunctionKind::Sum,
+        ),
+        map(
+            preceded(tag_no_case("AVG"), column()),
+            AggregateFunctionKind::Avg,
+        ),
+        map(
+            preceded(tag_no_case("MIN"), column()),
+            AggregateFunctionKind::Min,
+        ),
+        map(
+            preceded(tag_no_case("MAX"), column()),
+            AggregateFunctionKind::Max,
+        ),
+        map(
+            preceded(tag_no_case("MEDIAN"), column()),
+            AggregateFunctionKind::Median,
+        ),
+        map(
+            preceded(tag_no_case("PERCENTILE_CONT"), parse_percentile),
+            |(fraction, column)| AggregateFunctionKind::PercentileCont(fraction, column),
+        ),
+        map(
+            preceded(tag_no_case("PERCENTILE_DISC"), parse_percentile),
+            |(fraction, column)| AggregateFunctionKind::PercentileDisc(fraction, column),
+        ),
+        map(
+            preceded(
+                alt((tag_no_case("STDDEV_SAMP"), tag_no_case("STDDEV"))),
+                column(),
+            ),
+            AggregateFunctionKind::StddevSamp,
+        ),
+        map(
+            preceded(tag_no_case("STDDEV_POP"), column()),
+            AggregateFunctionKind::StddevPop,
+        ),
+        map(
+            preceded(
+                alt((tag_no_case("VAR_SAMP"), tag_no_case("VARIANCE"))),
+                column(),
+            ),
+            AggregateFunctionKind::VarSamp,
+        ),
+        map(
+            preceded(tag_no_case("VAR_POP"), column()),
+            AggregateFunctionKind::VarPop,
+        ),
+        map(
+            preceded(
+                tag_no_case("CORR"),
+                parse_arguments(tuple((parse_identifier, parse_comma, parse_identifier))),
+            ),
+            |(y, _, x)| AggregateFunctionKind::Corr(y, x),
+        ),
+        map(
+            preceded(
+                tag_no_case("ARRAY_AGG"),
+                parse_arguments(pair(
+                    parse_identifier,
+                    opt(preceded(multispace1, parse_order_by)),
+                )),
+            ),
+            |(column, order_by)| {
+                AggregateFunctionKind::ArrayAgg(column, order_by.unwrap_or_default())
+            },
+        ),
+        map(
+            preceded(
+                tag_no_case("STRING_AGG"),
+                parse_arguments(tuple((
+                    parse_identifier,
+                    parse_comma,
+                    parse_quoted,
+                    opt(preceded(multispace1, parse_order_by)),
+                ))),
+            ),
+            |(column, _, separator, order_by)| {
+                AggregateFunctionKind::StringAgg(column, separator, order_by.unwrap_or_default())
+            },
+        ),
+        map(
+            preceded(
+                tag_no_case("GROUPING"),
+                parse_arguments(separated_list1(parse_comma, parse_identifier)),
+            ),
+            AggregateFunctionKind::Grouping,
+        ),
+    ))(input)
+}
+
+fn parse_aggregate_function(input: &str) -> IResult<&str, AggregateFunction<'_>> {
+    map(
+        tuple((
+            parse_aggregate_function_kind,
+            opt(parse_filter),
+            opt(parse_alias),
+        )),
+        |(function, filter, alias)| AggregateFunction {
+            function,
+            filter,
+            alias,
+        },
+    )(input)
+}
+
+/// `FILTER (WHERE ...)` after an aggregate
+fn parse_filter(input: &str) -> IResult<&str, LogicalExpression<'_>> {
+    preceded(
+        tuple((multispace0, tag_no_case("FILTER"))),
+        parse_arguments(preceded(tag_no_case("WHERE"), |input| {
+            parse_conditions(input, &[])
+        })),
+    )(input)
+}
+
+fn parse_group_by(input: &str) -> IResult<&str, Option<GroupBy<'_>>> {
+    let (remaining, elements) = opt(preceded(
+        tuple((
+            multispace0,
+            tag_no_case("GROUP"),
+            multispace1,
+            tag_no_case("BY"),
+            multispace1,
+        )),
+        separated_list1(parse_comma, parse_grouping_element),
+    ))(input)?;
+
+    let elements = match elements {
+        Some(elements) => elements,
+        None => return Ok((remaining, None)),
+    };
+
+    // every combination of one set from each element, e.g. `a, ROLLUP(b)` is `(a, b)` and `(a)`
+    let sets = elements.iter().fold(vec![vec![]], |sets, element| {
+        sets.iter()
+            .flat_map(|set| {
+                element.iter().map(move |other| {
+                    let mut set: Vec<&str> = set.clone();
+                    set.extend(other);
+                    set
+                })
+            })
+            .collect()
+    });
+
+    let mut columns: Vec<&str> = vec![];
+    for column in sets.iter().flatten() {
+        if !columns.contains(column) {
+            columns.push(column);
+        }
+    }
+
+    Ok((remaining, Some(GroupBy { columns, sets })))
+}
+
+/// One item of GROUP BY, as the grouping sets it stands for
+fn parse_grouping_element(input: &str) -> IResult<&str, Vec<Vec<&str>>> {
+    let column_list = || parse_arguments(separated_list1(parse_comma, parse_identifier));
+
+    alt((
+        map(preceded(tag_no_case("ROLLUP"), column_list()), |columns| {
+            (0..=columns.len())
+                .rev()
+                .map(|i| columns[..i].to_vec())
+                .collect()
+        }),
+        map(preceded(tag_no_case("CUBE"), column_list()), |columns| {
+            (0..1usize << columns.len())
+                .rev()
+                .map(|mask| {
+                    columns
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| mask & (1 << (columns.len() - 1 - i)) != 0)
+                        .map(|(_, column)| *column)
+                        .collect()
+                })
+                .collect()
+        }),
+        preceded(
+            tuple((tag_no_case("GROUPING"), multispace1, tag_no_case("SETS"))),
+            parse_arguments(separated_list1(
+                parse_comma,
+                alt((
+                    column_list(),
+                    map(parse_arguments(multispace0), |_| vec![]),
+                    map(parse_identifier, |column| vec![column]),
+                )),
+            )),
+        ),
+        map(parse_identifier, |column| vec![vec![column]]),
+    ))(input)
+}
+
 fn parse_where<'a>(
     input: &'a str,
-    columns: &Vec<&str>,
-) -> IResult<&'a str, Option<LogicalExpression>> {
-    let (remaining, where_claus)= opt(tuple((multispace1, tag_no_case("WHERE"))))(input)?;
+    columns: &[&str],
+) -> IResult<&'a str, Option<LogicalExpression<'a>>> {
+    let (remaining, where_claus) = opt(tuple((multispace1, tag_no_case("WHERE"))))(input)?;
 
     match where_claus {
         Some((_, _)) => {
@@ -164,32 +1587,55 @@ fn parse_where<'a>(
         }
         None => Ok((remaining, None)),
     }
+}
+
+fn parse_qualify<'a>(input: &'a str, columns: &[&str]) -> IResult<&'a str, Option<Qualify<'a>>> {
+    let (body, qualify) = opt(tuple((multispace0, tag_no_case("QUALIFY"))))(input)?;
+    if qualify.is_none() {
+        return Ok((input, None));
+    }
 
+    let (remaining, mut conditions) = parse_conditions(body, columns)?;
+
+    let mut windows: Vec<WindowFunction> = vec![];
+    conditions.take_windows(&mut windows);
+
+    Ok((
+        remaining,
+        Some(Qualify {
+            windows,
+            conditions,
+        }),
+    ))
 }
 
 fn parse_logical_operator(input: &str) -> IResult<&str, &str> {
-    alt((tag_no_case("OR"), tag_no_case("AND")))(input)
+    // a whole word, so that e.g. `ORDER` is not read as `OR`
+    terminated(
+        alt((tag_no_case("OR"), tag_no_case("AND"))),
+        not(satisfy(is_identifier_char)),
+    )(input)
 }
 
 fn parse_conditions<'a>(
     input: &'a str,
-    columns: &Vec<&str>,
-) -> IResult<&'a str, LogicalExpression> {
+    columns: &[&str],
+) -> IResult<&'a str, LogicalExpression<'a>> {
     let (remaining, (predicate1, maybe_predicate2, _)) = tuple((
-        |input| parse_predicate(columns, input),
+        |input| parse_predicate_expression(columns, input),
         opt(tuple((multispace1, parse_logical_operator, |input| {
-            parse_predicate(columns, input)
+            parse_predicate_expression(columns, input)
         }))),
         multispace0,
     ))(input)?;
 
     let left = match maybe_predicate2 {
         Some((_, operator, predicate2)) => LogicalExpression::Condition(Condition {
-            left: Box::new(LogicalExpression::Predicate(predicate1)),
-            right: Box::new(LogicalExpression::Predicate(predicate2)),
+            left: Box::new(predicate1),
+            right: Box::new(predicate2),
             operator: LogicalOperator::from_str(operator).unwrap(),
         }),
-        None => LogicalExpression::Predicate(predicate1),
+        None => predicate1,
     };
 
     let additional_conditions = parse_logical_operator(remaining);
@@ -216,13 +1662,142 @@ fn parse_string_value(input: &str) -> IResult<&str, &str> {
         delimited(tag("\""), alphanumeric1, tag("\"")),
     ))(input)
 }
-fn parse_predicate<'a>(columns: &Vec<&str>, input: &'a str) -> IResult<&'a str, Predicate> {
-    let (remaining, (_, s1, _, comp, _, s2)) = tuple((
+
+fn parse_quoted(input: &str) -> IResult<&str, &str> {
+    alt((
+        delimited(tag("'"), take_until("'"), tag("'")),
+        delimited(tag("\""), take_until("\""), tag("\"")),
+    ))(input)
+}
+
+fn parse_temporal_base(input: &str) -> IResult<&str, TemporalBase> {
+    alt((
+        value(TemporalBase::CurrentDate, tag_no_case("CURRENT_DATE")),
+        value(
+            TemporalBase::CurrentTimestamp,
+            alt((tag_no_case("CURRENT_TIMESTAMP"), tag_no_case("NOW()"))),
+        ),
+        map_opt(
+            preceded(tuple((tag_no_case("DATE"), multispace0)), parse_quoted),
+            |s| Timestamp::parse_date(s).map(TemporalBase::Literal),
+        ),
+        map_opt(
+            preceded(tuple((tag_no_case("TIMESTAMP"), multispace0)), parse_quoted),
+            |s| Timestamp::parse(s).map(TemporalBase::Literal),
+        ),
+    ))(input)
+}
+
+fn parse_interval(input: &str) -> IResult<&str, Interval> {
+    map_opt(
+        tuple((
+            multispace0,
+            alt((tag("+"), tag("-"))),
+            multispace0,
+            tag_no_case("INTERVAL"),
+            multispace0,
+            parse_quoted,
+        )),
+        |(_, sign, _, _, _, interval)| {
+            let interval = Interval::parse(interval)?;
+            if sign == "-" {
+                Some(interval.negate())
+            } else {
+                Some(interval)
+            }
+        },
+    )(input)
+}
+
+fn parse_temporal_value(input: &str) -> IResult<&str, TemporalExpression> {
+    map_opt(
+        tuple((parse_temporal_base, many0(parse_interval))),
+        |(base, intervals)| {
+            let offset = intervals
+                .iter()
+                .try_fold(Interval::default(), |acc, interval| acc.combine(interval))?;
+            Some(TemporalExpression { base, offset })
+        },
+    )(input)
+}
+
+fn parse_parameter<'a>(input: &'a str) -> IResult<&'a str, Parameter<'a>> {
+    alt((
+        map(preceded(tag(":"), parse_identifier), Parameter::Named),
+        |input: &'a str| {
+            let (remaining, _) = tag("?")(input)?;
+            Ok((remaining, Parameter::Positional(remaining.len())))
+        },
+    ))(input)
+}
+
+fn parse_predicate_value(input: &str) -> IResult<&str, PredicateValue<'_>> {
+    alt((
+        map(parse_subquery, |query| {
+            PredicateValue::Subquery(ScalarSubquery {
+                query,
+                value: OnceCell::new(),
+            })
+        }),
+        map(parse_temporal_value, PredicateValue::Temporal),
+        map(parse_parameter, PredicateValue::Parameter),
+        map(parse_string_value, |s| {
+            PredicateValue::Literal(s.to_string())
+        }),
+    ))(input)
+}
+
+fn parse_predicate_expression<'a>(
+    columns: &[&str],
+    input: &'a str,
+) -> IResult<&'a str, LogicalExpression<'a>> {
+    alt((
+        map(parse_in_predicate, LogicalExpression::In),
+        map(
+            |input| parse_predicate(columns, input),
+            LogicalExpression::Predicate,
+        ),
+    ))(input)
+}
+
+fn parse_in_predicate(input: &str) -> IResult<&str, InPredicate<'_>> {
+    let (remaining, (_, column, _, negated, _, _, query)) = tuple((
+        multispace1,
+        parse_identifier,
+        multispace1,
+        opt(pair(tag_no_case("NOT"), multispace1)),
+        tag_no_case("IN"),
+        multispace0,
+        parse_subquery,
+    ))(input)?;
+
+    Ok((
+        remaining,
+        InPredicate {
+            column: column.to_string(),
+            negated: negated.is_some(),
+            query,
+            values: OnceCell::new(),
+        },
+    ))
+}
+
+fn parse_predicate<'a>(columns: &[&str], input: &'a str) -> IResult<&'a str, Predicate<'a>> {
+    let (remaining, (_, (s1, window), _, comp, _, value)) = tuple((
         multispace1,
         alt((
-            alphanumeric1,
-            delimited(tag("'"), alphanumeric1, tag("'")),
-            delimited(tag("\""), alphanumeric1, tag("\"")),
+            map(consumed(parse_window_function), |(text, mut window)| {
+                window.alias = Some(text);
+                (text, Some(Box::new(window)))
+            }),
+            map(
+                alt((
+                    parse_identifier,
+                    delimited(tag("'"), parse_identifier, tag("'")),
+                    delimited(tag("\""), parse_identifier, tag("\"")),
+                )),
+                |column| (column, None),
+            ),
         )),
         multispace0,
         alt((
@@ -235,29 +1810,265 @@ fn parse_predicate<'a>(columns: &Vec<&str>, input: &'a str) -> IResult<&'a str,
             tag("<>"),
         )),
         multispace0,
-        parse_string_value,
+        parse_predicate_value,
     ))(input)?;
 
-    if columns.contains(&s1) {
-        Ok((
+    // the column is on the left unless only the right side names a selected column,
+    // columns that do not exist are reported when the query runs
+    match value {
+        PredicateValue::Literal(s2) if !columns.contains(&s1) && columns.contains(&s2.as_str()) => {
+            Ok((
+                remaining,
+                Predicate {
+                    column: s2,
+                    operator: ComparisonOperator::from_str(comp).unwrap().mirror(),
+                    value: PredicateValue::Literal(s1.to_string()),
+                    window: None,
+                },
+            ))
+        }
+        value => Ok((
             remaining,
             Predicate {
                 column: s1.to_string(),
                 operator: ComparisonOperator::from_str(comp).unwrap(),
-                value: s2.to_string(),
-            },
-        ))
-    } else if columns.contains(&s2) {
-        Ok((
-            remaining,
-            Predicate {
-                column: s2.to_string(),
-                operator: ComparisonOperator::from_str(comp).unwrap(),
-                value: s1.to_string(),
+                value,
+                window,
             },
-        ))
-    } else {
-        eprintln!("{}: column {} not found", "error".bold().red(), s1);
-        exit(1);
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a single SELECT
+    fn select(input: &str) -> Box<Query<'_>> {
+        match parse_script(input).unwrap().1.pop() {
+            Some(CompoundQuery::Query(query)) => query,
+            query => panic!("not a single SELECT: {:?}", query),
+        }
+    }
+
+    #[test]
+    fn window_functions() {
+        let query = select(
+            "SELECT id, LAG(amount, 2, 0) OVER (PARTITION BY g ORDER BY id DESC) AS prev, \
+             SUM(amount) OVER (ORDER BY id ROWS BETWEEN 2 PRECEDING AND CURRENT ROW) AS total \
+             FROM t.csv",
+        );
+        let windows = query
+            .columns
+            .iter()
+            .filter_map(|item| match item {
+                SelectItem::Window(window) => Some(window),
+                _ => None,
+            })
+            .collect::<Vec<&WindowFunction>>();
+        assert_eq!(windows.len(), 2);
+
+        let lag = windows[0];
+        assert!(matches!(
+            &lag.function,
+            WindowFunctionKind::Lag(Offset {
+                column: "amount",
+                offset: 2,
+                default: Some("0")
+            })
+        ));
+        assert_eq!(lag.partition_by, ["g"]);
+        assert!(lag.order_by[0].descending);
+        assert_eq!(lag.name(), "prev");
+
+        let sum = windows[1];
+        assert!(matches!(
+            sum.frame,
+            Some(WindowFrame {
+                start: FrameBound::Preceding(2),
+                end: FrameBound::CurrentRow
+            })
+        ));
+    }
+
+    #[test]
+    fn set_operations() {
+        let (_, mut queries) = parse_script(
+            "SELECT a FROM x.csv UNION ALL SELECT a FROM y.csv EXCEPT SELECT a FROM z.csv",
+        )
+        .unwrap();
+        match queries.pop() {
+            Some(CompoundQuery::SetOperation(operation)) => {
+                assert!(matches!(operation.operator, SetOperator::Except));
+                assert!(matches!(
+                    *operation.left,
+                    CompoundQuery::SetOperation(SetOperation {
+                        operator: SetOperator::UnionAll,
+                        ..
+                    })
+                ));
+            }
+            query => panic!("not a set operation: {:?}", query),
+        }
+    }
+
+    #[test]
+    fn subqueries_and_ctes() {
+        let query = select(
+            "SELECT * FROM (SELECT * FROM t.csv) WHERE a IN (SELECT a FROM u.csv) \
+             AND b > (SELECT MAX(b) AS m FROM u.csv)",
+        );
+        assert!(matches!(query.from, TableSource::Subquery(_)));
+        match &query.conditions {
+            Some(LogicalExpression::Condition(condition)) => {
+                assert!(matches!(*condition.left, LogicalExpression::In(_)));
+                assert!(matches!(
+                    &*condition.right,
+                    LogicalExpression::Predicate(Predicate {
+                        value: PredicateValue::Subquery(_),
+                        ..
+                    })
+                ));
+            }
+            conditions => panic!("not a condition: {:?}", conditions),
+        }
+
+        let (_, mut queries) =
+            parse_script("WITH a AS (SELECT * FROM t.csv), b AS (SELECT * FROM a) SELECT * FROM b")
+                .unwrap();
+        match queries.pop() {
+            Some(CompoundQuery::With(with)) => {
+                let names = with.ctes.iter().map(|cte| cte.name).collect::<Vec<&str>>();
+                assert_eq!(names, ["a", "b"]);
+            }
+            query => panic!("not a WITH query: {:?}", query),
+        }
+    }
+
+    #[test]
+    fn aggregates() {
+        let query = select(
+            "SELECT g, PERCENTILE_CONT(0.9) WITHIN GROUP (ORDER BY v) AS p90, CORR(y, x), \
+             STRING_AGG(name, ';' ORDER BY name) AS names, COUNT(*) FILTER (WHERE v > 1) AS big \
+             FROM t.csv GROUP BY g",
+        );
+        let aggregates = query
+            .columns
+            .iter()
+            .filter_map(|item| match item {
+                SelectItem::Aggregate(aggregate) => Some(aggregate),
+                _ => None,
+            })
+            .collect::<Vec<&AggregateFunction>>();
+
+        assert!(matches!(
+            aggregates[0].function,
+            AggregateFunctionKind::PercentileCont(f, "v") if f == 0.9
+        ));
+        assert!(matches!(
+            aggregates[1].function,
+            AggregateFunctionKind::Corr("y", "x")
+        ));
+        assert_eq!(aggregates[1].name(), "corr");
+        assert!(matches!(
+            &aggregates[2].function,
+            AggregateFunctionKind::StringAgg("name", ";", order_by) if order_by.len() == 1
+        ));
+        assert!(matches!(
+            aggregates[3].function,
+            AggregateFunctionKind::Count(None)
+        ));
+        assert!(aggregates[3].filter.is_some());
+        assert_eq!(query.group_by.sets, [vec!["g"]]);
+    }
+
+    #[test]
+    fn grouping_sets() {
+        let sets = |group_by: &str| {
+            let input = format!("SELECT COUNT(*) FROM t.csv GROUP BY {}", group_by);
+            let query = select(&input);
+            query
+                .group_by
+                .sets
+                .iter()
+                .map(|set| set.join(","))
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(sets("ROLLUP(a, b)"), ["a,b", "a", ""]);
+        assert_eq!(sets("CUBE(a, b)"), ["a,b", "a", "b", ""]);
+        assert_eq!(sets("GROUPING SETS ((a, b), (a), ())"), ["a,b", "a", ""]);
+    }
+
+    #[test]
+    fn pivot_and_unpivot() {
+        let query = select(
+            "SELECT * FROM t.csv PIVOT (SUM(amount) FOR month IN ('Jan', 'Feb' AS february))",
+        );
+        match &query.reshape {
+            Some(Reshape::Pivot(pivot)) => {
+                assert_eq!(pivot.column, "month");
+                assert_eq!(pivot.values, [("Jan", "Jan"), ("Feb", "february")]);
+            }
+            reshape => panic!("not a pivot: {:?}", reshape),
+        }
+
+        let query = select("SELECT * FROM t.csv UNPIVOT (amount FOR month IN (Jan, Feb))");
+        match &query.reshape {
+            Some(Reshape::Unpivot(unpivot)) => {
+                assert_eq!((unpivot.value, unpivot.name), ("amount", "month"));
+                assert_eq!(unpivot.columns, ["Jan", "Feb"]);
+            }
+            reshape => panic!("not an unpivot: {:?}", reshape),
+        }
+    }
+
+    #[test]
+    fn qualify() {
+        let query = select(
+            "SELECT * FROM t.csv QUALIFY ROW_NUMBER() OVER (PARTITION BY g ORDER BY id) = 1 \
+             AND ROW_NUMBER() OVER (PARTITION BY g ORDER BY id) < 3",
+        );
+        let qualify = query.qualify.unwrap();
+        // the same window written twice is computed once
+        assert_eq!(qualify.windows.len(), 1);
+        assert_eq!(
+            qualify.windows[0].name(),
+            "ROW_NUMBER() OVER (PARTITION BY g ORDER BY id)"
+        );
+        assert_eq!(
+            qualify.conditions.referenced_columns(),
+            [qualify.windows[0].name(); 2]
+        );
+    }
+
+    #[test]
+    fn strip_comments_keeps_positions() {
+        let query = "SELECT a -- the a column\nFROM t.csv /* all rows */ WHERE b = ?";
+        let stripped = strip_comments(query);
+        assert_eq!(stripped.len(), query.len());
+        assert_eq!(
+            stripped,
+            "SELECT a                \nFROM t.csv                WHERE b = ?"
+        );
+        assert_eq!(stripped.find('?'), query.find('?'));
+    }
+
+    #[test]
+    fn strip_comments_counts_bytes() {
+        // a comment with multi-byte characters is replaced by as many bytes of spaces
+        let query = "SELECT a /* größe */ FROM t.csv -- €\nWHERE b = ?";
+        let stripped = strip_comments(query);
+        assert_eq!(stripped.len(), query.len());
+        assert_eq!(stripped.find("FROM"), query.find("FROM"));
+        assert_eq!(stripped.find('?'), query.rfind('?'));
+    }
+
+    #[test]
+    fn strip_comments_skips_quotes() {
+        let query = "SELECT * FROM 'a--b.csv' WHERE c = \"/*x*/\"";
+        assert_eq!(strip_comments(query), query);
+        // an unclosed comment runs to the end
+        assert_eq!(strip_comments("SELECT 1 /* x"), "SELECT 1     ");
     }
 }
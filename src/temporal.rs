@@ -0,0 +1,424 @@
+use core::fmt;
+use std::{
+    cmp::Ordering,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+// intervals are limited to this many years either way, so that adding them cannot overflow
+const MAX_INTERVAL_YEARS: i64 = 1_000_000;
+
+// dates are read with four digit years, which keeps the seconds since 1970 far from overflowing
+const MAX_YEAR: i64 = 9999;
+
+// days between the excel epoch (1899-12-30) and the unix epoch (1970-01-01)
+const EXCEL_EPOCH_OFFSET: f64 = 25_569.0;
+
+/// A point in time with second precision, stored as seconds since 1970-01-01 00:00:00.
+/// Timezones are not tracked, every value is treated as local to the spreadsheet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp {
+    seconds: i64,
+}
+
+/// A calendar aware duration, e.g. `INTERVAL '1 month 2 days'`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Interval {
+    months: i64,
+    days: i64,
+    seconds: i64,
+}
+
+#[derive(Debug, Clone)]
+pub enum TemporalBase {
+    Literal(Timestamp),
+    CurrentDate,
+    CurrentTimestamp,
+}
+
+/// A temporal value in a query, e.g. `CURRENT_DATE - INTERVAL '30 days'`
+#[derive(Debug)]
+pub struct TemporalExpression {
+    pub base: TemporalBase,
+    pub offset: Interval,
+}
+
+impl Timestamp {
+    pub fn now() -> Self {
+        let seconds = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        };
+
+        Self { seconds }
+    }
+
+    /// Parses `YYYY-MM-DD`, years past `MAX_YEAR` are not dates
+    pub fn parse_date(s: &str) -> Option<Self> {
+        let mut parts = s.trim().splitn(3, '-');
+        let year = parts.next()?.parse::<i64>().ok()?;
+        let month = parts.next()?.parse::<i64>().ok()?;
+        let day = parts.next()?.parse::<i64>().ok()?;
+
+        if !(0..=MAX_YEAR).contains(&year)
+            || !(1..=12).contains(&month)
+            || day < 1
+            || day > days_in_month(year, month)
+        {
+            return None;
+        }
+
+        Some(Self {
+            seconds: days_from_civil(year, month, day) * SECONDS_PER_DAY,
+        })
+    }
+
+    /// Parses `YYYY-MM-DD`, `YYYY-MM-DD HH:MM[:SS[.fff]]` or the ISO 8601 form with a `T`
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().trim_end_matches('Z');
+        if s.len() < 10 || !s.is_char_boundary(10) {
+            return None;
+        }
+
+        let (date, time) = s.split_at(10);
+        let date = Self::parse_date(date)?;
+        if time.is_empty() {
+            return Some(date);
+        }
+
+        let time = time.strip_prefix(|c| c == 'T' || c == ' ')?;
+        let time = time.split('.').next()?;
+        let mut parts = time.split(':');
+        let hours = parts.next()?.parse::<i64>().ok()?;
+        let minutes = parts.next()?.parse::<i64>().ok()?;
+        let seconds = match parts.next() {
+            Some(s) => s.parse::<i64>().ok()?,
+            None => 0,
+        };
+
+        if parts.next().is_some()
+            || !(0..24).contains(&hours)
+            || !(0..60).contains(&minutes)
+            || !(0..60).contains(&seconds)
+        {
+            return None;
+        }
+
+        Some(Self {
+            seconds: date.seconds + hours * 3600 + minutes * 60 + seconds,
+        })
+    }
+
+    /// Converts an excel serial date (days since 1899-12-30) to a timestamp
+    pub fn from_excel(serial: f64) -> Self {
+        Self {
+            seconds: ((serial - EXCEL_EPOCH_OFFSET) * SECONDS_PER_DAY as f64).round() as i64,
+        }
+    }
+
+    /// Truncates the timestamp to midnight of the same day
+    pub fn date(&self) -> Self {
+        Self {
+            seconds: self.seconds.div_euclid(SECONDS_PER_DAY) * SECONDS_PER_DAY,
+        }
+    }
+
+    pub fn add(&self, interval: &Interval) -> Self {
+        let mut seconds = self.seconds;
+
+        if interval.months != 0 {
+            let days = seconds.div_euclid(SECONDS_PER_DAY);
+            let time = seconds.rem_euclid(SECONDS_PER_DAY);
+            let (year, month, day) = civil_from_days(days);
+
+            let total_months = year * 12 + (month - 1) + interval.months;
+            let year = total_months.div_euclid(12);
+            let month = total_months.rem_euclid(12) + 1;
+            let day = day.min(days_in_month(year, month));
+
+            seconds = days_from_civil(year, month, day) * SECONDS_PER_DAY + time;
+        }
+
+        // intervals are bounded but the timestamp of a cell is not
+        Self {
+            seconds: seconds
+                .saturating_add(interval.days * SECONDS_PER_DAY)
+                .saturating_add(interval.seconds),
+        }
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = civil_from_days(self.seconds.div_euclid(SECONDS_PER_DAY));
+        let time = self.seconds.rem_euclid(SECONDS_PER_DAY);
+
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)?;
+        if time != 0 {
+            write!(
+                f,
+                " {:02}:{:02}:{:02}",
+                time / 3600,
+                time % 3600 / 60,
+                time % 60
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Interval {
+    /// Parses the body of an interval literal, e.g. `7 days` or `1 year 2 months`
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut interval = Self::default();
+        let mut tokens = s.split_whitespace();
+        let mut empty = true;
+
+        while let Some(amount) = tokens.next() {
+            let amount = amount.parse::<i64>().ok()?;
+            let unit = tokens.next()?.to_lowercase();
+
+            let (field, scale) = match unit.trim_end_matches('s') {
+                "year" => (&mut interval.months, 12),
+                "month" => (&mut interval.months, 1),
+                "week" => (&mut interval.days, 7),
+                "day" => (&mut interval.days, 1),
+                "hour" => (&mut interval.seconds, 3600),
+                "minute" => (&mut interval.seconds, 60),
+                "second" => (&mut interval.seconds, 1),
+                _ => return None,
+            };
+            *field = field.checked_add(amount.checked_mul(scale)?)?;
+            empty = false;
+        }
+
+        if empty {
+            None
+        } else {
+            interval.bounded()
+        }
+    }
+
+    /// The interval, unless it spans more than `MAX_INTERVAL_YEARS`
+    fn bounded(self) -> Option<Self> {
+        let days = MAX_INTERVAL_YEARS * 366;
+        if self.months.abs() > MAX_INTERVAL_YEARS * 12
+            || self.days.abs() > days
+            || self.seconds.abs() > days * SECONDS_PER_DAY
+        {
+            None
+        } else {
+            Some(self)
+        }
+    }
+
+    pub fn negate(&self) -> Self {
+        Self {
+            months: -self.months,
+            days: -self.days,
+            seconds: -self.seconds,
+        }
+    }
+
+    /// The sum of both intervals, `None` when it is too long
+    pub fn combine(&self, other: &Interval) -> Option<Self> {
+        Self {
+            months: self.months + other.months,
+            days: self.days + other.days,
+            seconds: self.seconds + other.seconds,
+        }
+        .bounded()
+    }
+}
+
+impl TemporalExpression {
+    pub fn evaluate(&self, now: &Timestamp) -> Timestamp {
+        let base = match self.base {
+            TemporalBase::Literal(ts) => ts,
+            TemporalBase::CurrentDate => now.date(),
+            TemporalBase::CurrentTimestamp => *now,
+        };
+
+        base.add(&self.offset)
+    }
+}
+
+/// Compares a cell against a timestamp, cells that are not dates are never comparable
+pub fn compare_to_cell(cell: &serde_json::Value, ts: &Timestamp) -> Option<Ordering> {
+    match cell {
+        serde_json::Value::String(s) => Timestamp::parse(s).map(|cell| cell.cmp(ts)),
+        _ => None,
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> Timestamp {
+        Timestamp::parse(s).unwrap()
+    }
+
+    fn interval(s: &str) -> Interval {
+        Interval::parse(s).unwrap()
+    }
+
+    #[test]
+    fn civil_days_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(civil_from_days(-719_468), (0, 3, 1));
+
+        for days in (-800_000..800_000).step_by(997) {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn leap_years() {
+        assert!(is_leap_year(2024));
+        assert!(is_leap_year(2000));
+        assert!(!is_leap_year(1900));
+        assert!(!is_leap_year(2023));
+
+        assert!(Timestamp::parse_date("2024-02-29").is_some());
+        assert!(Timestamp::parse_date("2023-02-29").is_none());
+        assert!(Timestamp::parse_date("1900-02-29").is_none());
+    }
+
+    #[test]
+    fn add_clamps_to_the_end_of_the_month() {
+        assert_eq!(
+            date("2024-01-31").add(&interval("1 month")),
+            date("2024-02-29")
+        );
+        assert_eq!(
+            date("2023-01-31").add(&interval("1 month")),
+            date("2023-02-28")
+        );
+        assert_eq!(
+            date("2024-02-29").add(&interval("1 year")),
+            date("2025-02-28")
+        );
+        assert_eq!(
+            date("2024-01-31 10:30").add(&interval("1 month 1 day")),
+            date("2024-03-01 10:30")
+        );
+    }
+
+    #[test]
+    fn negative_intervals() {
+        assert_eq!(
+            date("2024-03-31").add(&interval("1 month").negate()),
+            date("2024-02-29")
+        );
+        assert_eq!(
+            date("2024-01-01").add(&interval("-1 day")),
+            date("2023-12-31")
+        );
+        assert_eq!(
+            date("2024-01-01").add(&interval("2 hours").negate()),
+            date("2023-12-31 22:00")
+        );
+    }
+
+    #[test]
+    fn parse_timestamps() {
+        assert_eq!(date("2024-01-31").to_string(), "2024-01-31");
+        assert_eq!(date("2024-01-31 12:05").to_string(), "2024-01-31 12:05:00");
+        assert_eq!(
+            date("2024-01-31T12:05:09Z").to_string(),
+            "2024-01-31 12:05:09"
+        );
+        assert_eq!(date("2024-01-31 12:05:09.123"), date("2024-01-31 12:05:09"));
+
+        assert!(Timestamp::parse("2024-13-01").is_none());
+        assert!(Timestamp::parse("2024-01-31 24:00").is_none());
+        assert!(Timestamp::parse("2024-01-31 12:00:00:00").is_none());
+        assert!(Timestamp::parse("31/01/2024").is_none());
+    }
+
+    #[test]
+    fn parse_large_years() {
+        assert_eq!(
+            Timestamp::parse_date("9999-12-31").unwrap().to_string(),
+            "9999-12-31"
+        );
+        assert!(Timestamp::parse_date("10000-01-01").is_none());
+        assert!(Timestamp::parse_date("999999999999-01-01").is_none());
+        assert!(Timestamp::parse_date("9223372036854775807-01-01").is_none());
+    }
+
+    #[test]
+    fn parse_intervals() {
+        assert_eq!(interval("1 year 2 months"), interval("14 months"));
+        assert_eq!(interval("1 week"), interval("7 days"));
+        assert_eq!(
+            interval("1 hour 1 minute 1 second"),
+            interval("3661 seconds")
+        );
+
+        assert!(Interval::parse("").is_none());
+        assert!(Interval::parse("3 fortnights").is_none());
+        assert!(Interval::parse("999999999999999999 years").is_none());
+        assert!(Interval::parse("9223372036854775807 days 1 day").is_none());
+        assert!(interval("900000 years")
+            .combine(&interval("900000 years"))
+            .is_none());
+    }
+
+    #[test]
+    fn evaluate_against_a_fixed_now() {
+        let expression = TemporalExpression {
+            base: TemporalBase::CurrentDate,
+            offset: interval("30 days").negate(),
+        };
+
+        assert_eq!(
+            expression.evaluate(&date("2024-03-15 18:00")),
+            date("2024-02-14")
+        );
+    }
+}